@@ -1,6 +1,127 @@
-use smol::stream::StreamExt as _;
+// The local time zone is chrono's (TZ or /etc/localtime), the others are read from the system's
+// zoneinfo database (e.g.: /usr/share/zoneinfo/America/New_York) so they follow its updates.
 
-pub async fn statuses() -> impl smol::stream::Stream<Item = String> {
-  smol::Timer::interval_at(std::time::Instant::now(), std::time::Duration::from_secs(1))
-    .map(|_| chrono::offset::Local::now().format("%T %A %F").to_string())
+pub fn zone(name: &str) -> std::io::Result<tz::TimeZone> {
+  tz::TimeZone::from_posix_tz(name).map_err(std::io::Error::other)
+}
+
+// The names of days and months follow the locale, from the environment without one (e.g.:
+// LC_TIME=fr_FR.UTF-8).
+pub fn locale(locale: Option<&str>) -> chrono::Locale {
+  let locale = locale.map(str::to_string).or_else(|| {
+    ["LC_ALL", "LC_TIME", "LANG"]
+      .into_iter()
+      .filter_map(|variable| std::env::var(variable).ok())
+      .find(|locale| !locale.is_empty())
+  });
+  locale
+    .and_then(|locale| {
+      // Without the codeset (e.g.: ".UTF-8") nor the modifier (e.g.: "@euro").
+      let name = locale.split(['.', '@']).next().unwrap_or_default();
+      chrono::Locale::try_from(name).ok()
+    })
+    .unwrap_or(chrono::Locale::POSIX)
+}
+
+// A CLOCK_REALTIME timer, armed at wall-clock times: it fires on the boundary itself rather than
+// up to a period later (like a monotonic interval would), and when the boundary passed during a
+// suspend it fires on resume. The kernel cancels it when the clock is set (e.g.: by NTP).
+// https://man.archlinux.org/man/timerfd_create.2
+struct Timer(std::os::fd::OwnedFd);
+
+impl std::os::fd::AsFd for Timer {
+  fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+    self.0.as_fd()
+  }
+}
+
+impl Timer {
+  fn new() -> std::io::Result<Self> {
+    // SAFETY: no pointer is involved.
+    let fd = unsafe { libc::timerfd_create(libc::CLOCK_REALTIME, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC) };
+    match fd {
+      -1 => Err(std::io::Error::last_os_error()),
+      // SAFETY: the file descriptor was just opened and isn't owned by anything else.
+      fd => Ok(Self(unsafe { std::os::fd::FromRawFd::from_raw_fd(fd) })),
+    }
+  }
+
+  // At a time since the epoch, once.
+  fn arm(&self, at: std::time::Duration) -> std::io::Result<()> {
+    let value = libc::itimerspec {
+      it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+      it_value: libc::timespec {
+        tv_sec: at.as_secs() as libc::time_t,
+        tv_nsec: at.subsec_nanos().into(),
+      },
+    };
+    // SAFETY: the value outlives the call, the old value isn't asked for.
+    match unsafe {
+      libc::timerfd_settime(
+        std::os::fd::AsRawFd::as_raw_fd(&self.0),
+        libc::TFD_TIMER_ABSTIME | libc::TFD_TIMER_CANCEL_ON_SET,
+        &value,
+        std::ptr::null_mut(),
+      )
+    } {
+      -1 => Err(std::io::Error::last_os_error()),
+      _ => Ok(()),
+    }
+  }
+
+  // True when the timer expired, false when it was canceled because the clock was set.
+  fn read(&self) -> std::io::Result<bool> {
+    let mut expirations = 0u64;
+    // SAFETY: the buffer outlives the call and is as large as advertised.
+    match unsafe {
+      libc::read(
+        std::os::fd::AsRawFd::as_raw_fd(&self.0),
+        &mut expirations as *mut u64 as *mut libc::c_void,
+        std::mem::size_of::<u64>(),
+      )
+    } {
+      -1 => match std::io::Error::last_os_error() {
+        error if error.raw_os_error() == Some(libc::ECANCELED) => Ok(false),
+        error => Err(error),
+      },
+      _ => Ok(true),
+    }
+  }
+}
+
+// Ticks right away and then on each wall-clock second (or minute) boundary. After a change of the
+// clock, it ticks right away again and realigns. Resumes are handled by rebuilding the stream (see
+// sleeps() in main.rs).
+pub fn ticks(seconds: bool) -> impl smol::stream::Stream<Item = std::io::Result<()>> {
+  async_stream::try_stream! {
+  let period = std::time::Duration::from_secs(if seconds { 1 } else { 60 }).as_nanos();
+  let timer = smol::Async::new(Timer::new()?)?;
+  loop {
+    yield ();
+    let now = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos();
+    let next = (now / period + 1) * period;
+    timer.get_ref().arm(std::time::Duration::new((next / 1_000_000_000) as u64, (next % 1_000_000_000) as u32))?;
+    if !timer.read_with(|timer| timer.read()).await? {
+      log::debug!("The clock was set");
+    }
+  }
+  }
+}
+
+pub fn format(now: chrono::DateTime<chrono::Utc>, zone: Option<&tz::TimeZone>, format: &str, locale: chrono::Locale) -> String {
+  match zone {
+    Some(zone) => {
+      // There's always a local time type for the current time (only far away times may fail).
+      let offset = zone
+        .find_local_time_type(now.timestamp())
+        .map_or(0, |local_time_type| local_time_type.ut_offset());
+      // Unwrap: UTC offsets are always within a day.
+      let offset = chrono::FixedOffset::east_opt(offset).unwrap();
+      now.with_timezone(&offset).format_localized(format, locale).to_string()
+    }
+    None => now.with_timezone(&chrono::Local).format_localized(format, locale).to_string(),
+  }
 }
@@ -0,0 +1,574 @@
+// The configuration is read once at startup from $XDG_CONFIG_HOME/swaybar/config.toml (or
+// ~/.config/swaybar/config.toml). Without a file, the defaults below reproduce the bar as it was
+// before it became configurable:
+//
+//  [prometheus]
+//  url = "http://localhost:9090"
+//  timeout = 10
+//  points = 5
+//
+//  [[blocks]]
+//  type = "upload"
+//  device = "wlan0"
+//  maximum = 1_000_000
+//
+//  [[blocks]]
+//  type = "cpu"
+//  backend = "prometheus" # Or "native", to read /proc and /sys instead.
+//  thresholds = { orange = 0.3, red = 0.7 }
+//
+//  ...
+//
+// Arbitrary PromQL queries can be displayed too:
+//
+//  [[blocks]]
+//  type = "promql"
+//  label = "Requests"
+//  query = 'sum (rate(http_requests_total[1m])) by (instance)'
+//  unit = "/s"
+//  maximum = 100
+//  thresholds = { orange = 50, red = 80 }
+//  series = "instance"
+
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+  pub orange: f64,
+  pub red: f64,
+}
+
+impl Thresholds {
+  // For values that are worse when high (CPU utilization, temperature, ...).
+  fn validate_rising(&self) -> Result<(), String> {
+    match self.orange <= self.red {
+      true => Ok(()),
+      false => Err(format!(
+        "orange threshold ({}) must not be above red threshold ({})",
+        self.orange, self.red
+      )),
+    }
+  }
+
+  // For values that are worse when low (battery, signal, ...).
+  fn validate_falling(&self) -> Result<(), String> {
+    match self.orange >= self.red {
+      true => Ok(()),
+      false => Err(format!(
+        "orange threshold ({}) must not be below red threshold ({})",
+        self.orange, self.red
+      )),
+    }
+  }
+}
+
+fn validate_range(minimum: f64, maximum: f64) -> Result<(), String> {
+  match minimum < maximum {
+    true => Ok(()),
+    false => Err(format!("minimum ({minimum}) must be below maximum ({maximum})")),
+  }
+}
+
+fn clock_formats() -> Vec<String> {
+  vec!["%T %A %F".to_string()]
+}
+
+const fn clock_seconds() -> bool {
+  true
+}
+
+fn validate_format(format: &str, seconds: bool) -> Result<(), String> {
+  for item in chrono::format::StrftimeItems::new(format) {
+    match item {
+      chrono::format::Item::Error => return Err(format!("invalid format ({format:?})")),
+      chrono::format::Item::Numeric(chrono::format::Numeric::Second | chrono::format::Numeric::Timestamp, _) if !seconds => {
+        return Err(format!("format shows seconds but seconds is false ({format:?})"));
+      }
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+const fn cpu_thresholds() -> Thresholds {
+  Thresholds { orange: 0.3, red: 0.7 }
+}
+
+fn network_device() -> String {
+  "wlan0".to_string()
+}
+
+const fn network_maximum() -> f64 {
+  1_000_000. // 1M is interesting but not too large.
+}
+
+const fn promql_minimum() -> f64 {
+  0.
+}
+
+const fn promql_precision() -> usize {
+  2
+}
+
+const fn temperature_minimum() -> f64 {
+  30. // Unlikely to be less than 30°C.
+}
+
+const fn temperature_maximum() -> f64 {
+  100.
+}
+
+const fn temperature_thresholds() -> Thresholds {
+  Thresholds { orange: 50., red: 70. }
+}
+
+const fn upower_thresholds() -> Thresholds {
+  Thresholds { orange: 30., red: 10. }
+}
+
+fn upower_notifications() -> Vec<f64> {
+  vec![10., 5.]
+}
+
+const fn volume_step() -> u8 {
+  5
+}
+
+const fn volume_maximum() -> u16 {
+  100
+}
+
+// TODO: device specific
+//  https://github.com/bmegli/wifi-scan/issues/18
+//  https://www.intuitibits.com/2016/03/23/dbm-to-percent-conversion/
+const fn wifi_minimum() -> f64 {
+  -110.
+}
+
+const fn wifi_maximum() -> f64 {
+  -40.
+}
+
+const fn wifi_thresholds() -> Thresholds {
+  Thresholds { orange: 0.5, red: 0.3 }
+}
+
+// Where the CPU, network, temperature and Wi-Fi metrics come from.
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+  #[default]
+  Prometheus,
+  // Read from /proc and /sys (or nl80211), for machines without node_exporter and Prometheus.
+  Native,
+}
+
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+  // The value at the time of the query.
+  Instant,
+  // A sparkline of the last points.
+  #[default]
+  Range,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Block {
+  Bluez {
+    // For the battery of devices.
+    #[serde(default = "upower_thresholds")]
+    thresholds: Thresholds,
+  },
+  Clock {
+    // strftime formats (https://docs.rs/chrono/latest/chrono/format/strftime), a click cycles
+    // through them.
+    #[serde(default = "clock_formats")]
+    formats: Vec<String>,
+    // IANA time zones (e.g.: "America/New_York"), each gets a block after the local time's.
+    #[serde(default)]
+    zones: Vec<String>,
+    // For the names of days and months (e.g.: "fr_FR"), from the environment by default.
+    #[serde(default)]
+    locale: Option<String>,
+    // Without, the clock ticks on minute boundaries.
+    #[serde(default = "clock_seconds")]
+    seconds: bool,
+  },
+  Cpu {
+    #[serde(default)]
+    backend: Backend,
+    #[serde(default = "cpu_thresholds")]
+    thresholds: Thresholds,
+  },
+  Download {
+    #[serde(default)]
+    backend: Backend,
+    #[serde(default = "network_device")]
+    device: String,
+    #[serde(default = "network_maximum")]
+    maximum: f64,
+  },
+  Microphone {
+    // In percent, when scrolling.
+    #[serde(default = "volume_step")]
+    step: u8,
+    // In percent, scrolling won't go above.
+    #[serde(default = "volume_maximum")]
+    maximum: u16,
+  },
+  Promql {
+    label: String,
+    query: String,
+    #[serde(default)]
+    mode: Mode,
+    // Appended to the value (e.g.: "%", "/s", ...).
+    #[serde(default)]
+    unit: String,
+    // Digits after the decimal point.
+    #[serde(default = "promql_precision")]
+    precision: usize,
+    // The bounds of the bars (values outside are clamped).
+    #[serde(default = "promql_minimum")]
+    minimum: f64,
+    maximum: f64,
+    // Rising if orange is below red, falling otherwise.
+    #[serde(default)]
+    thresholds: Option<Thresholds>,
+    // Each series gets its own block, identified by the value of this label (e.g.: "instance").
+    #[serde(default)]
+    series: Option<String>,
+  },
+  Temperature {
+    #[serde(default)]
+    backend: Backend,
+    #[serde(default = "temperature_minimum")]
+    minimum: f64,
+    #[serde(default = "temperature_maximum")]
+    maximum: f64,
+    #[serde(default = "temperature_thresholds")]
+    thresholds: Thresholds,
+  },
+  Upload {
+    #[serde(default)]
+    backend: Backend,
+    #[serde(default = "network_device")]
+    device: String,
+    #[serde(default = "network_maximum")]
+    maximum: f64,
+  },
+  Upower {
+    #[serde(default = "upower_thresholds")]
+    thresholds: Thresholds,
+    // Only UPower's DisplayDevice, the composite of all batteries, instead of every device.
+    #[serde(default)]
+    composite: bool,
+    // In percent, a notification is sent when one is crossed while on battery.
+    #[serde(default = "upower_notifications")]
+    notifications: Vec<f64>,
+  },
+  Volume {
+    // In percent, when scrolling.
+    #[serde(default = "volume_step")]
+    step: u8,
+    // In percent, scrolling won't go above (150 would allow some amplification).
+    #[serde(default = "volume_maximum")]
+    maximum: u16,
+  },
+  Wifi {
+    #[serde(default)]
+    backend: Backend,
+    // The first station interface when unset (only with the native backend).
+    #[serde(default)]
+    interface: Option<String>,
+    // In dBm.
+    #[serde(default = "wifi_minimum")]
+    minimum: f64,
+    #[serde(default = "wifi_maximum")]
+    maximum: f64,
+    // On the signal interpolated between minimum and maximum.
+    #[serde(default = "wifi_thresholds")]
+    thresholds: Thresholds,
+  },
+}
+
+impl Block {
+  fn validate(&self) -> Result<(), String> {
+    match self {
+      Self::Clock {
+        formats,
+        zones,
+        locale,
+        seconds,
+      } => {
+        if formats.is_empty() {
+          return Err("formats must not be empty".to_string());
+        }
+        for format in formats {
+          validate_format(format, *seconds)?;
+        }
+        for zone in zones {
+          crate::clock::zone(zone).map_err(|error| format!("unknown time zone {zone:?} ({error})"))?;
+        }
+        match locale {
+          Some(locale) if chrono::Locale::try_from(locale.as_str()).is_err() => Err(format!("unknown locale {locale:?}")),
+          _ => Ok(()),
+        }
+      }
+      Self::Bluez { thresholds } => thresholds.validate_falling(),
+      Self::Upower {
+        thresholds, notifications, ..
+      } => match notifications.iter().find(|percentage| !(0. ..=100.).contains(*percentage)) {
+        Some(percentage) => Err(format!("notifications must be between 0 and 100 ({percentage})")),
+        None => thresholds.validate_falling(),
+      },
+      Self::Cpu { thresholds, .. } => thresholds.validate_rising(),
+      Self::Download { maximum, .. } | Self::Upload { maximum, .. } => validate_range(0., *maximum),
+      Self::Promql {
+        query, minimum, maximum, ..
+      } => match query.trim().is_empty() {
+        true => Err("query must not be empty".to_string()),
+        false => validate_range(*minimum, *maximum),
+      },
+      Self::Temperature {
+        minimum,
+        maximum,
+        thresholds,
+        ..
+      } => validate_range(*minimum, *maximum).and_then(|_| thresholds.validate_rising()),
+      Self::Microphone { step, maximum } | Self::Volume { step, maximum } => match (*step, *maximum) {
+        (0, _) => Err("step must be at least 1".to_string()),
+        (_, 0) => Err("maximum must be at least 1".to_string()),
+        _ => Ok(()),
+      },
+      Self::Wifi {
+        minimum,
+        maximum,
+        thresholds,
+        ..
+      } => validate_range(*minimum, *maximum).and_then(|_| thresholds.validate_falling()),
+    }
+  }
+}
+
+// Keeps secrets out of the logs.
+#[derive(Clone, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl std::fmt::Debug for Secret {
+  fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    formatter.write_str("Secret(..)")
+  }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Authorization {
+  Basic { username: String, password: Secret },
+  Bearer { token: Secret },
+}
+
+fn prometheus_url() -> String {
+  "http://localhost:9090".to_string()
+}
+
+const fn prometheus_timeout() -> f64 {
+  10.
+}
+
+// A day of samples, far more than a sparkline can usefully show.
+const POINTS: usize = 24 * 60;
+
+const fn prometheus_points() -> usize {
+  5
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Prometheus {
+  // The base URL (http or https), it may contain a path prefix (e.g.: behind a reverse proxy).
+  #[serde(default = "prometheus_url")]
+  pub url: String,
+  #[serde(default)]
+  pub authorization: Option<Authorization>,
+  // In seconds, for each request.
+  #[serde(default = "prometheus_timeout")]
+  pub timeout: f64,
+  // How many samples (one per prometheus::STEP) are drawn by sparklines.
+  #[serde(default = "prometheus_points")]
+  pub points: usize,
+}
+
+impl Default for Prometheus {
+  fn default() -> Self {
+    Self {
+      url: prometheus_url(),
+      authorization: None,
+      timeout: prometheus_timeout(),
+      points: prometheus_points(),
+    }
+  }
+}
+
+impl Prometheus {
+  fn validate(&self) -> Result<(), String> {
+    let url = self
+      .url
+      .parse::<http::Uri>()
+      .map_err(|error| format!("prometheus.url is invalid ({error})"))?;
+    if url.scheme() != Some(&http::uri::Scheme::HTTP) && url.scheme() != Some(&http::uri::Scheme::HTTPS) {
+      return Err(format!("prometheus.url must be http or https ({:?})", self.url));
+    }
+    if url.host().is_none() {
+      return Err(format!("prometheus.url has no host ({:?})", self.url));
+    }
+    // They would be sent in the Host header, the authorization is configured separately.
+    if url.authority().is_some_and(|authority| authority.as_str().contains('@')) {
+      // Without the URL, to keep them out of the logs.
+      return Err("prometheus.url can't have credentials, use prometheus.authorization".to_string());
+    }
+    if url.query().is_some() {
+      return Err(format!("prometheus.url can't have a query ({:?})", self.url));
+    }
+    if !(self.timeout > 0. && std::time::Duration::try_from_secs_f64(self.timeout).is_ok()) {
+      return Err(format!("prometheus.timeout must be positive and representable ({})", self.timeout));
+    }
+    if !(1..=POINTS).contains(&self.points) {
+      return Err(format!("prometheus.points must be between 1 and {POINTS} ({})", self.points));
+    }
+    Ok(())
+  }
+}
+
+fn blocks() -> Vec<Block> {
+  vec![
+    Block::Upload {
+      backend: Backend::default(),
+      device: network_device(),
+      maximum: network_maximum(),
+    },
+    Block::Download {
+      backend: Backend::default(),
+      device: network_device(),
+      maximum: network_maximum(),
+    },
+    Block::Wifi {
+      backend: Backend::default(),
+      interface: None,
+      minimum: wifi_minimum(),
+      maximum: wifi_maximum(),
+      thresholds: wifi_thresholds(),
+    },
+    Block::Temperature {
+      backend: Backend::default(),
+      minimum: temperature_minimum(),
+      maximum: temperature_maximum(),
+      thresholds: temperature_thresholds(),
+    },
+    Block::Cpu {
+      backend: Backend::default(),
+      thresholds: cpu_thresholds(),
+    },
+    Block::Upower {
+      thresholds: upower_thresholds(),
+      composite: false,
+      notifications: upower_notifications(),
+    },
+    Block::Volume {
+      step: volume_step(),
+      maximum: volume_maximum(),
+    },
+    Block::Bluez {
+      thresholds: upower_thresholds(),
+    },
+    Block::Clock {
+      formats: clock_formats(),
+      zones: Vec::new(),
+      locale: None,
+      seconds: clock_seconds(),
+    },
+  ]
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Errors {
+  // A block, first in the bar, counting the blocks in error.
+  #[serde(default)]
+  pub count: bool,
+}
+
+// The blocks are displayed in the order they are declared.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+  #[serde(default)]
+  pub prometheus: Prometheus,
+  #[serde(default)]
+  pub errors: Errors,
+  #[serde(default = "blocks")]
+  pub blocks: Vec<Block>,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      prometheus: Prometheus::default(),
+      errors: Errors::default(),
+      blocks: blocks(),
+    }
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("couldn't read {path:?}: {error}")]
+  IO { path: std::path::PathBuf, error: std::io::Error },
+  #[error("couldn't parse {path:?}: {error}")]
+  TOML { path: std::path::PathBuf, error: toml::de::Error },
+  #[error("invalid {path:?}: {message}")]
+  Invalid { path: std::path::PathBuf, message: String },
+}
+
+fn path() -> Option<std::path::PathBuf> {
+  std::env::var_os("XDG_CONFIG_HOME")
+    .filter(|directory| !directory.is_empty())
+    .map(std::path::PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|directory| std::path::Path::new(&directory).join(".config")))
+    .map(|directory| directory.join("swaybar").join("config.toml"))
+}
+
+impl Config {
+  fn validate(&self) -> Result<(), String> {
+    self.prometheus.validate()?;
+    for (index, block) in self.blocks.iter().enumerate() {
+      block.validate().map_err(|message| format!("blocks[{index}]: {message}"))?;
+    }
+    Ok(())
+  }
+
+  pub fn load() -> Result<Self, Error> {
+    let Some(path) = path() else {
+      log::debug!("No configuration directory, using the defaults");
+      return Ok(Self::default());
+    };
+    let config = match std::fs::read_to_string(&path) {
+      Ok(config) => config,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+        log::debug!("No configuration at {path:?}, using the defaults");
+        return Ok(Self::default());
+      }
+      Err(error) => return Err(Error::IO { path, error }),
+    };
+    let config: Self = match toml::from_str(&config) {
+      Ok(config) => config,
+      Err(error) => return Err(Error::TOML { path, error }),
+    };
+    if let Err(message) = config.validate() {
+      return Err(Error::Invalid { path, message });
+    }
+    log::debug!("Configuration from {path:?}: {config:?}");
+    Ok(config)
+  }
+}
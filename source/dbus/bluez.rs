@@ -1,9 +1,15 @@
 use smol::stream::StreamExt as _;
 
-#[zbus::proxy(default_service = "org.bluez", default_path = "/org/bluez/hci0", interface = "org.bluez.Adapter1")]
+// Connecting to an unreachable device can take a while, BlueZ gives up much later.
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
+
+// Adapters are under /org/bluez (e.g.: /org/bluez/hci0) and come and go (e.g.: a USB dongle).
+#[zbus::proxy(default_service = "org.bluez", interface = "org.bluez.Adapter1")]
 trait Adapter1 {
   #[zbus(property)]
   fn powered(&self) -> zbus::Result<bool>;
+  #[zbus(property)]
+  fn set_powered(&self, powered: bool) -> zbus::Result<()>;
 }
 
 #[zbus::proxy(default_service = "org.bluez", interface = "org.bluez.Device1")]
@@ -18,58 +24,83 @@ trait Device1 {
   fn connected(&self) -> zbus::Result<bool>;
   #[zbus(property)]
   fn address(&self) -> zbus::Result<String>;
+  #[zbus(property)]
+  fn adapter(&self) -> zbus::Result<zvariant::OwnedObjectPath>;
   // The RSSI property is spotty. It does appear during a scan, for some devices.
   // Worst case, 'hcitool rssi' can retrieve it.
 }
 
+// Only present on connected devices that report it (headsets, mice, ...).
+#[zbus::proxy(default_service = "org.bluez", interface = "org.bluez.Battery1")]
+trait Battery1 {
+  #[zbus(property)]
+  fn percentage(&self) -> zbus::Result<u8>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  #[error("IO error")]
+  IO(#[from] std::io::Error),
+  #[error("ZBus error")]
+  ZBus(#[from] zbus::Error),
+  // The radio is blocked by a physical switch, there's nothing we can do.
+  #[error("Hard blocked")]
+  HardBlocked,
+  #[error("Timeout")]
+  Timeout,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Progress {
+  Connecting,
+  Disconnecting,
+}
+
 #[derive(Debug)]
 pub struct DeviceStatus {
   pub name: String,
   pub connected: bool,
+  // In percent.
+  pub battery: Option<u8>,
   pub path: zvariant::OwnedObjectPath,
 }
 
 #[derive(Debug)]
-pub struct Status {
+pub struct AdapterStatus {
+  pub path: zvariant::OwnedObjectPath,
   pub powered: bool,
   pub devices: Vec<DeviceStatus>,
 }
 
-pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Status>> {
-  async_stream::try_stream! {
-  let connection = zbus::Connection::system().await?;
-  let adapter = Adapter1Proxy::new(&connection).await?;
+#[derive(Debug)]
+pub struct Status {
+  // Sorted by path, hci0 first.
+  pub adapters: Vec<AdapterStatus>,
+}
 
-  let properties = zbus::fdo::PropertiesProxy::builder(&connection)
-    .destination(adapter.0.destination())?
-    .path(adapter.0.path())?
-    .build()
-    .await?;
-  // signal time=1664648181.086398 sender=:1.298 -> destination=(null destination) serial=3655 path=/org/bluez/hci0; interface=org.freedesktop.DBus.Properties; member=PropertiesChanged
-  //    string "org.bluez.Adapter1"
-  //    array [
-  //       dict entry(
-  //          string "Powered"
-  //          variant             boolean true
-  //       )
-  //    ]
-  //    array [
-  //    ]
-  let mut adapter_properties = properties
-    .receive_properties_changed()
-    .await?
-    .map(|properties| {
-      log::trace!("Adapter properties:\n{:?}", properties.message());
+fn properties_changed<'p>(
+  properties: zbus::fdo::PropertiesChangedStream<'p>,
+  kind: &'static str,
+) -> futures_util::stream::LocalBoxStream<'p, bool> {
+  properties
+    .map(move |properties| {
+      log::trace!("{kind} properties:\n{:?}", properties.message());
       false
     })
-    .boxed_local();
+    .boxed_local()
+}
+
+pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Status>> {
+  async_stream::try_stream! {
+  let connection = zbus::Connection::system().await?;
 
   let object_manager = zbus::fdo::ObjectManagerProxy::builder(&connection)
-    .destination(adapter.0.destination())?
+    .destination("org.bluez")?
     .path("/")?
     .build()
     .await?;
-  // During a scan, BlueZ adds all devices to the tree, firing InterfacesAdded signals.
+  // During a scan, BlueZ adds all devices to the tree, firing InterfacesAdded signals. So does
+  // plugging in an adapter.
   //
   // signal time=1664647927.437581 sender=:1.298 -> destination=(null destination) serial=3608 path=/; interface=org.freedesktop.DBus.ObjectManager; member=InterfacesAdded
   //    object path "/org/bluez/hci0/dev_00_11_22_33_44_55
@@ -97,29 +128,63 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Status>
     true
   }).boxed_local();
 
+  let mut adapters = std::collections::HashMap::new();
+  let mut adapters_properties = std::collections::HashMap::new();
   let mut devices = std::collections::HashMap::new();
   let mut devices_properties = std::collections::HashMap::new();
+  let mut batteries = std::collections::HashMap::new();
 
   loop {
-    let mut paths = std::collections::HashSet::new();
+    let mut adapter_paths = std::collections::HashSet::new();
+    let mut device_paths = std::collections::HashSet::new();
+    let mut battery_paths = std::collections::HashSet::new();
 
-    // Crawl all the devices under the adapter.
+    // Crawl all the adapters and the devices under them.
     // Trusted devices are always present.
     // https://git.kernel.org/pub/scm/bluetooth/bluez.git/tree/test/list-devices
     for (path, interface) in object_manager.get_managed_objects().await? {
       for (interface, _) in interface {
-        if interface == "org.bluez.Device1" {
-          paths.insert(path.clone());
+        match interface.as_str() {
+          "org.bluez.Adapter1" => _ = adapter_paths.insert(path.clone()),
+          "org.bluez.Device1" => _ = device_paths.insert(path.clone()),
+          "org.bluez.Battery1" => _ = battery_paths.insert(path.clone()),
+          _ => {}
         }
       }
     }
 
+    let previous_paths: std::collections::HashSet<_> = adapters.keys().cloned().collect();
+    for path in previous_paths.difference(&adapter_paths) {
+      adapters_properties.remove(path);
+      adapters.remove(path);
+    }
+    for path in adapter_paths.difference(&previous_paths) {
+      let adapter = Adapter1Proxy::builder(&connection).path(path.to_string())?.build().await?;
+      // signal time=1664648181.086398 sender=:1.298 -> destination=(null destination) serial=3655 path=/org/bluez/hci0; interface=org.freedesktop.DBus.Properties; member=PropertiesChanged
+      //    string "org.bluez.Adapter1"
+      //    array [
+      //       dict entry(
+      //          string "Powered"
+      //          variant             boolean true
+      //       )
+      //    ]
+      //    array [
+      //    ]
+      let properties = zbus::fdo::PropertiesProxy::builder(&connection)
+        .destination(adapter.0.destination())?
+        .path(adapter.0.path())?
+        .build()
+        .await?;
+      adapters_properties.insert(path.clone(), properties_changed(properties.receive_properties_changed().await?, "Adapter"));
+      adapters.insert(path.clone(), adapter);
+    }
+
     let previous_paths: std::collections::HashSet<_> = devices.keys().cloned().collect();
-    for path in previous_paths.difference(&paths) {
+    for path in previous_paths.difference(&device_paths) {
       devices_properties.remove(path);
       devices.remove(path);
     }
-    for path in paths.difference(&previous_paths) {
+    for path in device_paths.difference(&previous_paths) {
       let device = Device1Proxy::builder(&connection).path(path.to_string())?.build().await?;
       if !device.paired().await? {
         // I only care about connecting and disconnecting known devices.
@@ -141,27 +206,48 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Status>
         .path(device.0.path())?
         .build()
         .await?;
-      devices_properties.insert(path.clone(), properties.receive_properties_changed().await?.map(|properties| {
-        log::trace!("Device properties:\n{:?}", properties.message());
-        false
-      }).boxed_local());
-      devices.insert(path.clone(), device);
+      devices_properties.insert(path.clone(), properties_changed(properties.receive_properties_changed().await?, "Device"));
+      // The adapter a device belongs to never changes.
+      let adapter = device.adapter().await?;
+      devices.insert(path.clone(), (device, adapter));
+    }
+
+    // The battery shows up (and goes away) with the connection, under the device's path: its
+    // changes come through the device's PropertiesChanged stream.
+    batteries.retain(|path, _| battery_paths.contains(path));
+    for path in battery_paths {
+      if let std::collections::hash_map::Entry::Vacant(entry) = batteries.entry(path) {
+        let battery = Battery1Proxy::builder(&connection).path(entry.key().to_string())?.build().await?;
+        entry.insert(battery);
+      }
     }
 
     loop {
-      let mut device_statuses = Vec::new();
-      for device in devices.values() {
-        device_statuses.push(DeviceStatus {
-          name: device.name().await?,
-          connected: device.connected().await?,
-          path: device.0.path().to_owned().into(),
+      let mut adapter_statuses = Vec::new();
+      for (path, adapter) in &adapters {
+        let mut device_statuses = Vec::new();
+        for (device_path, (device, _)) in devices.iter().filter(|(_, (_, adapter))| adapter == path) {
+          let battery = match batteries.get(device_path) {
+            Some(battery) => Some(battery.percentage().await?),
+            None => None,
+          };
+          device_statuses.push(DeviceStatus {
+            name: device.name().await?,
+            connected: device.connected().await?,
+            battery,
+            path: device_path.clone(),
+          });
+        }
+        adapter_statuses.push(AdapterStatus {
+          path: path.clone(),
+          powered: adapter.powered().await?,
+          devices: device_statuses,
         });
       }
-      let status = Status {
-        powered: adapter.powered().await?,
-        devices: device_statuses,
+      adapter_statuses.sort_by(|adapter0, adapter1| adapter0.path.as_str().cmp(adapter1.path.as_str()));
+      yield Status {
+        adapters: adapter_statuses,
       };
-      yield status;
 
       // In the Tokio version I was using a StreamMap. zbus also depends on futures-util so I don't
       // feel too bad to use select_all here (it allocates every time, though).
@@ -170,8 +256,9 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Status>
         //  - more than one property can change,
         //  - more entries can be added under a device.
         // TODO: the streams need to be boxed(_local) to make the type checker happy here, can I do otherwise?
-        [adapter_properties.next(), interfaces_added.next(), interfaces_removed.next()]
+        [interfaces_added.next(), interfaces_removed.next()]
         .into_iter()
+        .chain(adapters_properties.values_mut().map(|adapter_properties| adapter_properties.next()))
         .chain(devices_properties.values_mut().map(|device_properties| device_properties.next())),
       )
       .await;
@@ -184,32 +271,98 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Status>
   }
 }
 
-pub async fn toggle(device: Option<&zvariant::ObjectPath<'_>>) -> zbus::Result<()> {
-  if let Some(path) = device {
-    let device = Device1Proxy::builder(&zbus::Connection::system().await?)
-      .path(path)?
-      .build()
-      .await?;
-    return if device.connected().await? {
-      device.disconnect().await
-    } else {
-      device.connect().await
-    };
+enum Object<'c> {
+  Adapter(Adapter1Proxy<'c>),
+  Device(Device1Proxy<'c>),
+}
+
+// Blocks are either adapters or devices, the tree tells which. None when it's gone (e.g.: an
+// unplugged adapter).
+async fn object<'c>(connection: &'c zbus::Connection, path: &zvariant::ObjectPath<'_>) -> zbus::Result<Option<Object<'c>>> {
+  let object_manager = zbus::fdo::ObjectManagerProxy::builder(connection)
+    .destination("org.bluez")?
+    .path("/")?
+    .build()
+    .await?;
+  let Some((_, interfaces)) = object_manager
+    .get_managed_objects()
+    .await?
+    .into_iter()
+    .find(|(object, _)| object.as_str() == path.as_str())
+  else {
+    return Ok(None);
+  };
+  for interface in interfaces.keys() {
+    match interface.as_str() {
+      "org.bluez.Adapter1" => {
+        return Ok(Some(Object::Adapter(
+          Adapter1Proxy::builder(connection).path(path.to_owned())?.build().await?,
+        )));
+      }
+      "org.bluez.Device1" => {
+        return Ok(Some(Object::Device(
+          Device1Proxy::builder(connection).path(path.to_owned())?.build().await?,
+        )));
+      }
+      _ => {}
+    }
   }
+  Ok(None)
+}
+
+// Connects or disconnects a device, powers an adapter on or off. For devices, started is called
+// before asking BlueZ, which only answers once it's done.
+pub async fn toggle(path: &zvariant::ObjectPath<'_>, started: impl FnOnce(Progress)) -> Result<(), Error> {
+  let connection = zbus::Connection::system().await?;
+  match object(&connection, path).await? {
+    Some(Object::Device(device)) => {
+      let connected = device.connected().await?;
+      started(if connected { Progress::Disconnecting } else { Progress::Connecting });
+      smol::future::or(
+        async {
+          match connected {
+            true => Ok(device.disconnect().await?),
+            false => Ok(device.connect().await?),
+          }
+        },
+        async {
+          smol::Timer::after(TIMEOUT).await;
+          Err(Error::Timeout)
+        },
+      )
+      .await
+    }
+    Some(Object::Adapter(adapter)) => power(&adapter).await,
+    None => Ok(()),
+  }
+}
 
-  // There is no method on the adapter to do that.
-  smol::process::Command::new("bash")
-    .args([
-      "-c",
-      r#"(if bluetoothctl show | grep 'Powered: no'; then
-           bluetoothctl power on
-         else
-           bluetoothctl power off
-         fi) &> /dev/null
-    "#,
-    ])
-    .status()
-    .await
-    .map(|_| ())
-    .map_err(|error| zbus::Error::InputOutput(std::sync::Arc::new(error)))
+// Powers on or off the adapter a device belongs to (or the adapter itself).
+pub async fn toggle_adapter(path: &zvariant::ObjectPath<'_>) -> Result<(), Error> {
+  let connection = zbus::Connection::system().await?;
+  match object(&connection, path).await? {
+    Some(Object::Device(device)) => {
+      let adapter = Adapter1Proxy::builder(&connection).path(device.adapter().await?)?.build().await?;
+      power(&adapter).await
+    }
+    Some(Object::Adapter(adapter)) => power(&adapter).await,
+    None => Ok(()),
+  }
+}
+
+async fn power(adapter: &Adapter1Proxy<'_>) -> Result<(), Error> {
+  let powered = adapter.powered().await?;
+  if !powered {
+    // Adapters are named after their HCI device (e.g.: /org/bluez/hci0).
+    let device = std::path::Path::new("/sys/class/bluetooth").join(adapter.0.path().rsplit('/').next().unwrap_or_default());
+    match crate::rfkill::switch(&device).await? {
+      Some(crate::rfkill::Switch { hard: true, .. }) => return Err(Error::HardBlocked),
+      Some(crate::rfkill::Switch { index, soft: true, .. }) => {
+        log::debug!("Unblocking rfkill{index} for {device:?}");
+        crate::rfkill::unblock(index).await?;
+      }
+      _ => {}
+    }
+  }
+  Ok(adapter.set_powered(!powered).await?)
 }
@@ -0,0 +1,40 @@
+use smol::stream::StreamExt as _;
+
+#[zbus::proxy(
+  default_service = "org.freedesktop.login1",
+  default_path = "/org/freedesktop/login1",
+  interface = "org.freedesktop.login1.Manager"
+)]
+trait Manager {
+  // https://systemd.io/INHIBITOR_LOCKS/
+  fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<zvariant::OwnedFd>;
+  // Sent with true before suspending (or hibernating) and with false after resuming.
+  #[zbus(signal)]
+  fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+pub struct Logind(ManagerProxy<'static>);
+
+impl Logind {
+  pub async fn new() -> zbus::Result<Self> {
+    Ok(Self(ManagerProxy::new(&zbus::Connection::system().await?).await?))
+  }
+
+  // True before suspending (or hibernating), false after resuming.
+  pub async fn sleeps(&self) -> zbus::Result<impl smol::stream::Stream<Item = bool>> {
+    // signal time=1664650014.180735 sender=:1.2 -> destination=(null destination) serial=1650 path=/org/freedesktop/login1; interface=org.freedesktop.login1.Manager; member=PrepareForSleep
+    //    boolean false
+    Ok(self.0.receive_prepare_for_sleep().await?.filter_map(|signal| match signal.args() {
+      Ok(arguments) => Some(arguments.start),
+      Err(error) => {
+        log::warn!("Invalid PrepareForSleep signal: {error:?}");
+        None
+      }
+    }))
+  }
+
+  // Sleeping is delayed until the lock is dropped (or for InhibitDelayMaxSec, 5s by default).
+  pub async fn inhibit(&self) -> zbus::Result<zvariant::OwnedFd> {
+    self.0.inhibit("sleep", "swaybar", "Pausing the blocks", "delay").await
+  }
+}
@@ -1,2 +1,4 @@
 pub mod bluez;
+pub mod logind;
+pub mod notifications;
 pub mod upower;
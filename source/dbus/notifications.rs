@@ -0,0 +1,46 @@
+// Notifications are shown over fullscreen windows, unlike the bar.
+// https://specifications.freedesktop.org/notification-spec/latest/
+
+#[zbus::proxy(
+  default_service = "org.freedesktop.Notifications",
+  default_path = "/org/freedesktop/Notifications",
+  interface = "org.freedesktop.Notifications"
+)]
+trait Notifications {
+  #[allow(clippy::too_many_arguments)] // As specified.
+  fn notify(
+    &self,
+    app_name: &str,
+    replaces_id: u32,
+    app_icon: &str,
+    summary: &str,
+    body: &str,
+    actions: &[&str],
+    hints: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    expire_timeout: i32,
+  ) -> zbus::Result<u32>;
+  fn close_notification(&self, id: u32) -> zbus::Result<()>;
+}
+
+// A critical notification stays until it's closed. A previous notification (its id, 0 for none)
+// is replaced rather than stacked. Returns the id of the notification.
+pub async fn critical(summary: &str, body: &str, replaces: u32) -> zbus::Result<u32> {
+  let notifications = NotificationsProxy::new(&zbus::Connection::session().await?).await?;
+  notifications
+    .notify(
+      "swaybar",
+      replaces,
+      "battery-caution",
+      summary,
+      body,
+      &[],
+      std::collections::HashMap::from([("urgency", zvariant::Value::U8(2))]),
+      0,
+    )
+    .await
+}
+
+pub async fn close(id: u32) -> zbus::Result<()> {
+  let notifications = NotificationsProxy::new(&zbus::Connection::session().await?).await?;
+  notifications.close_notification(id).await
+}
@@ -7,6 +7,11 @@ use smol::stream::StreamExt as _;
 )]
 trait UPower {
   fn enumerate_devices(&self) -> zbus::Result<Vec<zvariant::OwnedObjectPath>>;
+  // The composite of all the batteries (e.g.: a ThinkPad's internal and removable ones).
+  fn get_display_device(&self) -> zbus::Result<zvariant::OwnedObjectPath>;
+  // Whether the system runs on battery, rather than on AC or a UPS on AC.
+  #[zbus(property)]
+  fn on_battery(&self) -> zbus::Result<bool>;
   #[zbus(signal)]
   fn device_added(&self) -> zbus::Result<()>;
   #[zbus(signal)]
@@ -23,15 +28,96 @@ trait Device {
   fn model(&self) -> zbus::Result<String>;
   #[zbus(property)]
   fn percentage(&self) -> zbus::Result<f64>;
+  #[zbus(property)]
+  fn state(&self) -> zbus::Result<u32>;
+  // In seconds, 0 when unknown.
+  #[zbus(property)]
+  fn time_to_empty(&self) -> zbus::Result<i64>;
+  #[zbus(property)]
+  fn time_to_full(&self) -> zbus::Result<i64>;
+  // In W.
+  #[zbus(property)]
+  fn energy_rate(&self) -> zbus::Result<f64>;
+  #[zbus(property)]
+  fn warning_level(&self) -> zbus::Result<u32>;
 }
 
-#[derive(Debug)]
+// https://upower.freedesktop.org/docs/Device.html#Device:State
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+  Unknown,
+  Charging,
+  Discharging,
+  Empty,
+  FullyCharged,
+  PendingCharge,
+  PendingDischarge,
+}
+
+impl From<u32> for State {
+  fn from(state: u32) -> Self {
+    match state {
+      1 => Self::Charging,
+      2 => Self::Discharging,
+      3 => Self::Empty,
+      4 => Self::FullyCharged,
+      5 => Self::PendingCharge,
+      6 => Self::PendingDischarge,
+      _ => Self::Unknown,
+    }
+  }
+}
+
+// https://upower.freedesktop.org/docs/Device.html#Device:WarningLevel
+// Decided by UPower (from its own configuration), ordered by severity.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum WarningLevel {
+  Unknown,
+  None,
+  Discharging, // Only for UPSes.
+  Low,
+  Critical,
+  Action, // The system is about to act (e.g.: hibernate).
+}
+
+impl From<u32> for WarningLevel {
+  fn from(level: u32) -> Self {
+    match level {
+      1 => Self::None,
+      2 => Self::Discharging,
+      3 => Self::Low,
+      4 => Self::Critical,
+      5 => Self::Action,
+      _ => Self::Unknown,
+    }
+  }
+}
+
+fn duration(seconds: i64) -> Option<std::time::Duration> {
+  u64::try_from(seconds)
+    .ok()
+    .filter(|seconds| *seconds > 0)
+    .map(std::time::Duration::from_secs)
+}
+
+#[derive(Clone, Debug)]
 pub struct Status {
+  pub path: zvariant::OwnedObjectPath,
+  // The model, empty for the DisplayDevice.
   pub device: String,
   pub percentage: f64,
+  pub state: State,
+  pub time_to_empty: Option<std::time::Duration>,
+  pub time_to_full: Option<std::time::Duration>,
+  // In W, positive whether charging or discharging.
+  pub energy_rate: f64,
+  pub warning_level: WarningLevel,
+  // UPower's, the same for all the devices.
+  pub on_battery: bool,
 }
 
-pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Vec<Status>>> {
+// With composite, only the DisplayDevice is followed.
+pub async fn statuses(composite: bool) -> impl smol::stream::Stream<Item = zbus::Result<Vec<Status>>> {
   async_stream::try_stream! {
   let connection = zbus::Connection::system().await?;
   let upower = UPowerProxy::new(&connection).await?;
@@ -48,6 +134,10 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Vec<Sta
     log::trace!("Device removed:\n{:?}", device.message());
     true
   }).boxed_local();
+  let mut on_battery_changed = upower.receive_on_battery_changed().await.map(|_| {
+    log::trace!("OnBattery changed");
+    false
+  }).boxed_local();
 
   let mut devices = std::collections::HashMap::new();
   let mut devices_properties = std::collections::HashMap::new();
@@ -61,7 +151,10 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Vec<Sta
     //    anyway.
     // Fortunately, by using EnumerateDevices we can work around that and we don't have to
     // ignore the fake DisplayDevice (https://upower.freedesktop.org/docs/UPower.html).
-    let paths: std::collections::HashSet<_> = upower.enumerate_devices().await?.iter().cloned().collect();
+    let paths: std::collections::HashSet<_> = match composite {
+      true => std::iter::once(upower.get_display_device().await?).collect(),
+      false => upower.enumerate_devices().await?.iter().cloned().collect(),
+    };
 
     let previous_paths: std::collections::HashSet<_> = devices.keys().cloned().collect();
     for path in previous_paths.difference(&paths) {
@@ -102,17 +195,25 @@ pub async fn statuses() -> impl smol::stream::Stream<Item = zbus::Result<Vec<Sta
 
     loop {
       let mut statuses = Vec::new();
-      for device in devices.values() {
+      let on_battery = upower.on_battery().await?;
+      for (path, device) in &devices {
         statuses.push(Status {
+          path: path.clone(),
           device: device.model().await?,
           percentage: device.percentage().await?,
+          state: device.state().await?.into(),
+          time_to_empty: duration(device.time_to_empty().await?),
+          time_to_full: duration(device.time_to_full().await?),
+          energy_rate: device.energy_rate().await?.abs(),
+          warning_level: device.warning_level().await?.into(),
+          on_battery,
         })
       }
       statuses.sort_by(|status1, status2| status1.device.cmp(&status2.device));
       yield statuses;
 
       let (rebuild, index, _) = futures_util::future::select_all(
-        [device_added.next(), device_removed.next()]
+        [device_added.next(), device_removed.next(), on_battery_changed.next()]
         .into_iter()
         .chain(devices_properties.values_mut().map(|device_properties| device_properties.next())),
       )
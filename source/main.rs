@@ -4,8 +4,14 @@ use smol::{
 };
 
 mod clock;
+mod config;
 mod dbus;
+mod markup;
+mod native;
+mod nl80211;
 mod prometheus;
+mod pulse;
+mod rfkill;
 mod volume;
 
 fn interpolate(minimum: f64, maximum: f64, value: f64) -> f64 {
@@ -26,42 +32,189 @@ fn bars1(minimum: f64, maximum: f64, value: f64) -> char {
   BARS1[(interpolated * (BARS1.len() - 1) as f64) as usize]
 }
 
+// The widest a sparkline followed by some text can be.
+fn sparkline_width(points: usize, text: &str) -> MinWidth {
+  MinWidth::Text(format!("{}{text}", BARS0[BARS0.len() - 1].to_string().repeat(points)))
+}
+
+#[derive(Clone, Copy)]
 enum Color {
   Unspecified,
   Orange,
   Red,
 }
 
-fn color<TS: ToString>(string: TS, color: Color) -> String {
-  format!(
-    r#"<span color="{}">{}</span>"#,
-    match color {
-      Color::Unspecified => return string.to_string(),
-      Color::Orange => "orange",
-      Color::Red => "red",
-    },
-    string.to_string()
-  )
+fn color(markup: markup::Markup, color: Color) -> markup::Markup {
+  let color = match color {
+    Color::Unspecified => return markup,
+    Color::Orange => "orange",
+    Color::Red => "red",
+  };
+  markup.span(&[("color", color)])
+}
+
+// For values that are worse when high.
+fn rising(thresholds: &config::Thresholds, value: f64) -> Color {
+  match value {
+    value if value >= thresholds.red => Color::Red,
+    value if value >= thresholds.orange => Color::Orange,
+    _ => Color::Unspecified,
+  }
+}
+
+// For values that are worse when low.
+fn falling(thresholds: &config::Thresholds, value: f64) -> Color {
+  match value {
+    value if value <= thresholds.red => Color::Red,
+    value if value <= thresholds.orange => Color::Orange,
+    _ => Color::Unspecified,
+  }
 }
 
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(untagged)]
+enum MinWidth {
+  #[allow(dead_code)] // Not used by any block yet.
+  Pixels(u32),
+  // The block will be at least as wide as this text would be.
+  Text(String),
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Align {
+  #[allow(dead_code)] // The default.
+  Left,
+  Right,
+  #[allow(dead_code)] // Not used by any block yet.
+  Center,
+}
+
+// https://man.archlinux.org/man/swaybar-protocol.7#BODY
+// Unset fields are left out so swaybar uses its defaults (or the ones from the bar's configuration).
 #[derive(Clone, Debug, serde::Serialize)]
 struct Block {
+  full_text: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  short_text: Option<String>,
+  // Colors are #RRGGBB or #RRGGBBAA.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  color: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  background: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  border: Option<String>,
+  // Border widths, in pixels.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  border_top: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  border_bottom: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  border_left: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  border_right: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  min_width: Option<MinWidth>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  align: Option<Align>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  urgent: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   instance: Option<String>,
-  full_text: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  separator: Option<bool>,
+  // In pixels, after the block.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  separator_block_width: Option<u32>,
   markup: String,
 }
 
 impl Block {
-  fn new(full_text: &str) -> Self {
+  fn new(full_text: markup::Markup) -> Self {
     Self {
+      full_text: full_text.into_string(),
+      short_text: None,
+      color: None,
+      background: None,
+      border: None,
+      border_top: None,
+      border_bottom: None,
+      border_left: None,
+      border_right: None,
+      min_width: None,
+      align: None,
+      urgent: None,
       name: None,
       instance: None,
-      full_text: full_text.to_string(),
+      separator: None,
+      separator_block_width: None,
       markup: "pango".to_string(),
     }
   }
 
+  // Used when the bar doesn't have enough room for the full text.
+  fn short_text(mut self, short_text: markup::Markup) -> Self {
+    self.short_text = Some(short_text.into_string());
+    self
+  }
+
+  // Of the whole text, parts of it are colored with color() instead.
+  #[allow(dead_code)] // Not used by any block yet.
+  fn color(mut self, color: &str) -> Self {
+    self.color = Some(color.to_string());
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn background(mut self, background: &str) -> Self {
+    self.background = Some(background.to_string());
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn border(mut self, border: &str) -> Self {
+    self.border = Some(border.to_string());
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn border_top(mut self, width: u32) -> Self {
+    self.border_top = Some(width);
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn border_bottom(mut self, width: u32) -> Self {
+    self.border_bottom = Some(width);
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn border_left(mut self, width: u32) -> Self {
+    self.border_left = Some(width);
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn border_right(mut self, width: u32) -> Self {
+    self.border_right = Some(width);
+    self
+  }
+
+  // Padding is added according to the alignment, avoiding jitter when the text's width changes.
+  fn min_width(mut self, min_width: MinWidth, align: Align) -> Self {
+    self.min_width = Some(min_width);
+    self.align = Some(align);
+    self
+  }
+
+  fn urgent(mut self, urgent: bool) -> Self {
+    self.urgent = urgent.then_some(true);
+    self
+  }
+
   fn name(mut self, name: &str) -> Self {
     self.name = Some(name.to_string());
     self
@@ -72,20 +225,18 @@ impl Block {
     self.instance = Some(instance.to_string());
     self
   }
-}
 
-#[derive(Debug, Default)]
-struct Blocks {
-  bluez: Vec<Block>,
-  clock: Option<Block>,
-  cpu: Option<Block>,
-  download: Option<Block>,
-  error: Option<Block>,
-  temperature: Option<Block>,
-  upload: Option<Block>,
-  upower: Vec<Block>,
-  volume: Option<Block>,
-  wifi: Option<Block>,
+  #[allow(dead_code)] // Not used by any block yet.
+  fn separator(mut self, separator: bool) -> Self {
+    self.separator = Some(separator);
+    self
+  }
+
+  #[allow(dead_code)] // Not used by any block yet.
+  fn separator_block_width(mut self, width: u32) -> Self {
+    self.separator_block_width = Some(width);
+    self
+  }
 }
 
 // TODO: This workaround is unfortunate, is that really necessary?
@@ -109,6 +260,8 @@ impl<T: ?Sized> RefCellGuard<T> {
 
 #[derive(Debug, thiserror::Error)]
 enum BlockUpdateError {
+  #[error("Bluez error")]
+  Bluez(#[from] dbus::bluez::Error),
   #[error("IO error")]
   IO(#[from] std::io::Error),
   #[error("JSON error")]
@@ -123,43 +276,203 @@ enum BlockUpdateError {
 enum BlockUpdate {
   Click(Click),
   Error(BlockUpdateError),
+  Pause,
   Publish,
   Rebuild,
+  Resume,
+  Sleep,
+  Wake,
 }
 
 type BlockUpdateStream<'b> = std::pin::Pin<Box<dyn smol::stream::Stream<Item = BlockUpdate> + 'b>>;
-type BlocksGuard = RefCellGuard<Blocks>;
+type LocalFuture<'b, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + 'b>>;
+// The blocks currently displayed by a provider.
+type Slot = RefCellGuard<Vec<Block>>;
 
-async fn bluez(blocks: &BlocksGuard) -> BlockUpdateStream<'_> {
-  dbus::bluez::statuses()
-    .await
-    .map(|status| {
-      let (bluez, update) = match status {
-        Ok(status) => {
-          let block_name = "bluez";
-          (
-            match status {
-              dbus::bluez::Status { powered: false, .. } => vec![Block::new(&format!("{} Bluetooth", BARS0[0])).name(block_name)],
-              dbus::bluez::Status {
-                powered: true, devices, ..
-              } if devices.is_empty() => vec![Block::new(&format!("{} Bluetooth", BARS0[BARS0.len() - 1])).name(block_name)],
-              dbus::bluez::Status { devices, .. } => devices
-                .iter()
-                .map(|dbus::bluez::DeviceStatus { name, connected, path }| {
-                  let bar = if *connected { BARS0[BARS0.len() - 1] } else { BARS0[0] }; // https://stackoverflow.com/a/73301647
-                  Block::new(&format!("{bar} {name}")).name(block_name).instance(path.as_str())
-                })
-                .collect(),
+// A provider owns a slot in the bar: its stream updates the slot (an ended stream is rebuilt
+// after a backoff period) and clicks on blocks carrying its name are routed back to it.
+trait Provider {
+  fn name(&self) -> &str;
+
+  // For humans (e.g.: in errors).
+  fn label(&self) -> &str;
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>>;
+
+  fn click<'b>(&'b self, _click: &'b Click) -> Option<LocalFuture<'b, Result<(), BlockUpdateError>>> {
+    None
+  }
+}
+
+struct Bluez {
+  thresholds: config::Thresholds,
+  // The devices being connected or disconnected, until BlueZ answers.
+  progress: RefCellGuard<std::collections::HashMap<String, dbus::bluez::Progress>>,
+  // Wakes the stream up when the above changes.
+  progressed: (async_channel::Sender<()>, async_channel::Receiver<()>),
+}
+
+impl Bluez {
+  fn new(thresholds: config::Thresholds) -> Self {
+    Self {
+      thresholds,
+      progress: RefCellGuard::new(std::collections::HashMap::new()),
+      progressed: async_channel::bounded(1),
+    }
+  }
+
+  fn set_progress(&self, path: &str, progress: Option<dbus::bluez::Progress>) {
+    self.progress.borrow_mut(|devices| match progress {
+      Some(progress) => devices.insert(path.to_string(), progress),
+      None => devices.remove(path),
+    });
+    // A full channel means a wakeup is already pending.
+    let _ = self.progressed.0.try_send(());
+  }
+}
+
+impl Provider for Bluez {
+  fn name(&self) -> &str {
+    "bluez"
+  }
+
+  fn label(&self) -> &str {
+    "Bluetooth"
+  }
+
+  fn stream<'b>(&'b self, _executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(bluez(self, slot))
+  }
+
+  fn click<'b>(&'b self, Click { instance, button, .. }: &'b Click) -> Option<LocalFuture<'b, Result<(), BlockUpdateError>>> {
+    // Unwrap: the ObjectPath was converted to a string when declaring the block.
+    let path = zvariant::ObjectPath::try_from(instance.clone()?).unwrap();
+    match button {
+      1 => Some(Box::pin(async move {
+        let toggled = dbus::bluez::toggle(&path, |progress| self.set_progress(path.as_str(), Some(progress))).await;
+        self.set_progress(path.as_str(), None);
+        Ok(toggled?)
+      })),
+      // Even from a device block, the adapter it belongs to.
+      3 => Some(Box::pin(async move { Ok(dbus::bluez::toggle_adapter(&path).await?) })),
+      _ => None,
+    }
+  }
+}
+
+fn bluez_blocks(provider: &Bluez, status: &dbus::bluez::Status) -> Vec<Block> {
+  let thresholds = &provider.thresholds;
+  status
+    .adapters
+    .iter()
+    .flat_map(|adapter| {
+      // Adapters are told apart only when there's more than one.
+      let label = match status.adapters.len() {
+        1 => "Bluetooth".to_string(),
+        _ => format!("Bluetooth {}", adapter.path.rsplit('/').next().unwrap_or_default()),
+      };
+      match adapter {
+        dbus::bluez::AdapterStatus { powered: false, path, .. } => {
+          vec![
+            Block::new(markup::Markup::new().text(format!("{} {label}", BARS0[0])))
+              .name(provider.name())
+              .instance(path.as_str()),
+          ]
+        }
+        dbus::bluez::AdapterStatus {
+          powered: true,
+          devices,
+          path,
+        } if devices.is_empty() => {
+          vec![
+            Block::new(markup::Markup::new().text(format!("{} {label}", BARS0[BARS0.len() - 1])))
+              .name(provider.name())
+              .instance(path.as_str()),
+          ]
+        }
+        dbus::bluez::AdapterStatus { devices, .. } => devices
+          .iter()
+          .map(
+            |dbus::bluez::DeviceStatus {
+               name,
+               connected,
+               battery,
+               path,
+             }| {
+              let bar = if *connected { BARS0[BARS0.len() - 1] } else { BARS0[0] }; // https://stackoverflow.com/a/73301647
+              let text = markup::Markup::new().text(format!("{bar} {name}"));
+              let text = match provider.progress.borrow(|progress| progress.get(path.as_str()).copied()) {
+                Some(dbus::bluez::Progress::Connecting) => text.text(" connecting…"),
+                Some(dbus::bluez::Progress::Disconnecting) => text.text(" disconnecting…"),
+                None => text,
+              };
+              // Like the UPower block.
+              let (text, urgent) = match battery {
+                Some(percentage) => {
+                  let level = falling(thresholds, (*percentage).into());
+                  let urgent = matches!(level, Color::Red);
+                  let battery = color(markup::Markup::new().text(bars1(0., 100., (*percentage).into())), level);
+                  (text.text(" ").push(battery).text(format!(" {percentage}%")), urgent)
+                }
+                None => (text, false),
+              };
+              Block::new(text).urgent(urgent).name(provider.name()).instance(path.as_str())
             },
-            BlockUpdate::Publish,
           )
+          .collect(),
+      }
+    })
+    .collect()
+}
+
+// BlueZ's statuses, along with the progress of the clicks.
+async fn bluez<'b>(provider: &'b Bluez, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  let mut statuses = dbus::bluez::statuses().await.boxed_local();
+  async_stream::stream! {
+    enum Event {
+      Status(Option<zbus::Result<dbus::bluez::Status>>),
+      Progress,
+    }
+
+    let mut status = None;
+    loop {
+      let event = smol::future::or(async { Event::Status(statuses.next().await) }, async {
+        provider.progressed.1.recv().await.unwrap(); // Unwrap: the sender won't close.
+        Event::Progress
+      })
+      .await;
+      match event {
+        Event::Status(Some(Ok(next))) => status = Some(next),
+        Event::Status(Some(Err(error))) => {
+          slot.borrow_mut(|blocks| blocks.clear());
+          yield BlockUpdate::Error(error.into());
+          break;
         }
-        Err(error) => (Vec::new(), BlockUpdate::Error(error.into())),
+        Event::Status(None) => break,
+        Event::Progress => {}
+      }
+      let Some(status) = &status else {
+        continue;
       };
-      blocks.borrow_mut(|blocks| blocks.bluez = bluez);
-      update
-    })
-    .boxed_local()
+      slot.borrow_mut(|blocks| *blocks = bluez_blocks(provider, status));
+      yield BlockUpdate::Publish;
+    }
+  }
+  .boxed_local()
+}
+
+// Several providers can have the same name (e.g.: two clocks in different formats), the index of
+// the provider in the registry is appended to the names of its blocks when publishing, so that
+// clicks are routed back to the right one.
+fn route(index: usize, mut block: Block) -> Block {
+  block.name = block.name.map(|name| format!("{name}#{index}"));
+  block
+}
+
+// The name of the provider and its index, from the name of a clicked block.
+fn routed(name: &str) -> Option<(&str, usize)> {
+  let (name, index) = name.rsplit_once('#')?;
+  Some((name, index.parse().ok()?))
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -191,422 +504,1350 @@ async fn clicks<'b>() -> BlockUpdateStream<'b> {
     .boxed_local()
 }
 
-async fn clock(blocks: &BlocksGuard) -> BlockUpdateStream<'_> {
-  clock::statuses()
-    .await
-    .map(|clock| {
-      blocks.borrow_mut(|blocks| blocks.clock = Some(Block::new(&clock)));
-      BlockUpdate::Publish
+struct Clock {
+  formats: Vec<String>,
+  // Besides the local one.
+  zones: Vec<String>,
+  locale: chrono::Locale,
+  seconds: bool,
+  // The index of the current format, clicks cycle through them.
+  format: std::cell::Cell<usize>,
+  // Wakes the stream up after a click.
+  clicked: (async_channel::Sender<()>, async_channel::Receiver<()>),
+}
+
+impl Provider for Clock {
+  fn name(&self) -> &str {
+    "clock"
+  }
+
+  fn label(&self) -> &str {
+    "Clock"
+  }
+
+  fn stream<'b>(&'b self, _executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(clock(self, slot))
+  }
+
+  fn click<'b>(&'b self, Click { button, .. }: &'b Click) -> Option<LocalFuture<'b, Result<(), BlockUpdateError>>> {
+    match button {
+      1 => {
+        self.format.set((self.format.get() + 1) % self.formats.len());
+        // A full channel means a wakeup is already pending.
+        let _ = self.clicked.0.try_send(());
+        Some(Box::pin(async { Ok(()) }))
+      }
+      _ => None,
+    }
+  }
+}
+
+async fn clock<'b>(provider: &'b Clock, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  async_stream::stream! {
+    // Read again on each rebuild, the database may have been updated.
+    let zones = match provider.zones.iter().map(|zone| clock::zone(zone)).collect::<std::io::Result<Vec<_>>>() {
+      Ok(zones) => zones,
+      Err(error) => {
+        yield BlockUpdate::Error(error.into());
+        return;
+      }
+    };
+    let mut ticks = clock::ticks(provider.seconds).boxed_local();
+    loop {
+      let tick = smol::future::or(ticks.next(), async {
+        provider.clicked.1.recv().await.unwrap(); // Unwrap: the sender won't close.
+        Some(Ok(()))
+      })
+      .await;
+      match tick {
+        Some(Ok(())) => {}
+        Some(Err(error)) => {
+          slot.borrow_mut(|blocks| blocks.clear());
+          yield BlockUpdate::Error(error.into());
+          break;
+        }
+        None => break,
+      }
+      let now = chrono::Utc::now();
+      let format = &provider.formats[provider.format.get()];
+      let blocks = std::iter::once(
+        Block::new(markup::Markup::new().text(clock::format(now, None, format, provider.locale))).name(provider.name()),
+      )
+      .chain(provider.zones.iter().zip(&zones).map(|(name, zone)| {
+        // e.g.: "America/New_York" is labeled "New York".
+        let label = name.rsplit('/').next().unwrap_or_default().replace('_', " ");
+        Block::new(markup::Markup::new().text(format!("{} {label}", clock::format(now, Some(zone), format, provider.locale))))
+          .name(provider.name())
+          .instance(name)
+      }))
+      .collect();
+      slot.borrow_mut(|slot| *slot = blocks);
+      yield BlockUpdate::Publish;
+    }
+  }
+  .boxed_local()
+}
+
+// swaybar sends these when the bar is hidden or shown again (e.g.: in hide mode).
+// SIGSTOP and SIGCONT (the defaults) would stop the process without giving it a chance to react.
+const STOP_SIGNAL: async_signal::Signal = async_signal::Signal::Usr1;
+const CONT_SIGNAL: async_signal::Signal = async_signal::Signal::Usr2;
+
+// Unlike other streams, this one is never rebuilt: unregistering the handlers would restore the
+// default action of the signals, which is to terminate the process.
+fn signals<'b>(signals: async_signal::Signals) -> BlockUpdateStream<'b> {
+  signals
+    .filter_map(|signal| {
+      log::debug!("Signal: {signal:?}");
+      match signal {
+        Ok(STOP_SIGNAL) => Some(BlockUpdate::Pause),
+        Ok(CONT_SIGNAL) => Some(BlockUpdate::Resume),
+        Ok(_) => None,
+        Err(error) => Some(BlockUpdate::Error(error.into())),
+      }
     })
     .boxed_local()
 }
 
-struct ErrorSender(async_channel::Sender<BlockUpdateError>);
-
-impl ErrorSender {
-  // Because it's not asynchronous and because the queue is bounded, only
-  // async_channel::Sender::force_send should be used to avoid any deadlock.
-  // It doesn't matter much if an error is lost when too many are fired at once.
-  pub fn force_send(&self, error: BlockUpdateError) -> Result<(), async_channel::SendError<BlockUpdateError>> {
-    if let Some(error) = self.0.force_send(error)? {
-      log::debug!("Dropped unreceived error: {error:?}");
+// Streams are dropped before sleeping and rebuilt after resuming: the values are stale by then
+// and connections (D-Bus, PulseAudio, Prometheus) may have died in the meantime. Like signals(),
+// this one is never rebuilt.
+fn sleeps<'b>() -> BlockUpdateStream<'b> {
+  async_stream::stream! {
+    match dbus::logind::Logind::new().await {
+      Ok(logind) => match logind.sleeps().await {
+        Ok(sleeps) => {
+          let mut sleeps = sleeps.boxed_local();
+          // Held while awake, so the streams are dropped before sleeping.
+          let inhibit = || async {
+            logind
+              .inhibit()
+              .await
+              .inspect_err(|error| log::warn!("Failed to delay sleeping: {error:?}"))
+              .ok()
+          };
+          let mut lock = inhibit().await;
+          while let Some(start) = sleeps.next().await {
+            log::debug!("PrepareForSleep: {start:?}");
+            match start {
+              true => {
+                yield BlockUpdate::Sleep;
+                // Only polled again once the streams are dropped.
+                lock = None;
+              }
+              false => {
+                lock = inhibit().await;
+                yield BlockUpdate::Wake;
+              }
+            }
+          }
+          drop(lock);
+          log::warn!("No more PrepareForSleep signals");
+        }
+        Err(error) => log::warn!("Failed to follow sleeps: {error:?}"),
+      },
+      Err(error) => log::warn!("Failed to follow sleeps: {error:?}"),
     }
-    Ok(())
+    smol::future::pending::<()>().await;
   }
+  .boxed_local()
 }
 
-async fn error(blocks: &BlocksGuard) -> (ErrorSender, BlockUpdateStream<'_>) {
+const PUBLISH_WINDOW: std::time::Duration = std::time::Duration::from_millis(25);
+
+// Ended streams are rebuilt once no error was received for a while. Each error restarts the
+// timer, so that a flapping stream doesn't get rebuilt in a loop.
+const BACKOFF: std::time::Duration = std::time::Duration::from_secs(10);
+
+// The sender is notified of errors, the stream yields the rebuilds. Because the queue is bounded,
+// only async_channel::Sender::try_send should be used (an error is already pending when it's
+// full).
+fn backoff<'b>() -> (async_channel::Sender<()>, BlockUpdateStream<'b>) {
   let (sender, receiver) = async_channel::bounded(1);
   let stream = async_stream::stream! {
-    #[derive(Debug)]
-    enum Event {
-      Receive(BlockUpdateError),
-      Tick,
+    loop {
+      // The timer only runs after an error, to avoid needless wakeups (e.g.: while the bar is
+      // hidden).
+      receiver.recv().await.unwrap(); // Unwrap: the sender won't close.
+      while smol::future::or(
+        async {
+          receiver.recv().await.unwrap(); // Unwrap: the sender won't close.
+          true
+        },
+        async {
+          smol::Timer::after(BACKOFF).await;
+          false
+        },
+      )
+      .await
+      {}
+      yield BlockUpdate::Rebuild;
     }
+  }
+  .boxed_local();
+  (sender, stream)
+}
 
-    let mut receiver = receiver.map(Event::Receive).boxed_local();
-    let mut timer = smol::Timer::interval_at(std::time::Instant::now(), std::time::Duration::from_secs(1))
-    .map(|_| Event::Tick)
-    .boxed_local();
-    let mut ticks = 0;
-    loop {
-      let (event, index, _) = futures_util::future::select_all([receiver.next(), timer.next()]).await;
-      assert!(event.is_some(), "End of stream {index}");
-      if let Some(event) = event {
-        match event {
-          Event::Receive(error) => {
-            ticks = 0;
-            blocks.borrow_mut(|blocks| blocks.error = Some(Block::new(&color(format!("{error:?}"), Color::Red))));
-            yield BlockUpdate::Publish;
-          }
-          Event::Tick => {
-            ticks += <bool as Into<u32>>::into(blocks.borrow_mut(|blocks| blocks.error.is_some()));
-            if ticks >= 10 {
-              ticks = 0;
-              blocks.borrow_mut(|blocks| blocks.error = None);
-              yield BlockUpdate::Rebuild;
-            }
-          }
-        };
+// The error and its sources, for the log.
+fn chain(error: &dyn std::error::Error) -> String {
+  let mut chain = error.to_string();
+  let mut source = error.source();
+  while let Some(error) = source {
+    // Some errors already display their source (e.g.: zbus::Error::InputOutput).
+    let error_text = error.to_string();
+    if !chain.ends_with(&error_text) {
+      chain.push_str(&format!(": {error_text}"));
+    }
+    source = error.source();
+  }
+  chain
+}
+
+// A provider's error is displayed in place: instead of its blocks when its stream failed (until
+// the rebuilt stream publishes), after them when a click failed (until the next rebuild).
+#[derive(Clone)]
+enum Failure {
+  Stream(Block),
+  Click(Block),
+}
+
+// Messages from servers can be long (e.g.: a PromQL parse error quoting the query), the bar only
+// shows their beginning.
+const MESSAGE: usize = 40;
+
+fn shorten(text: &str) -> String {
+  match text.char_indices().nth(MESSAGE) {
+    Some((index, _)) => format!("{}…", &text[..index]),
+    None => text.to_string(),
+  }
+}
+
+impl Failure {
+  // What went wrong, in a few words: the outer error only says which kind of provider failed.
+  fn kind(error: &BlockUpdateError) -> String {
+    match error {
+      BlockUpdateError::Bluez(dbus::bluez::Error::Timeout) | BlockUpdateError::Prometheus(prometheus::Error::Timeout) => {
+        "timeout".to_string()
+      }
+      BlockUpdateError::Prometheus(prometheus::Error::HTTPStatus(status)) => format!("HTTP {}", status.as_u16()),
+      // The message comes from the server, it's escaped like any text.
+      BlockUpdateError::Prometheus(prometheus::Error::Prometheus { kind, message, .. }) => format!("{kind}: {}", shorten(message)),
+      // Otherwise the innermost error (e.g.: "Connection refused (os error 111)" rather than "IO
+      // error"), or the error itself (e.g.: "Hard blocked").
+      error => {
+        let mut source: &dyn std::error::Error = error;
+        while let Some(inner) = source.source() {
+          source = inner;
+        }
+        shorten(&source.to_string())
       }
     }
   }
-  .boxed_local();
-  (ErrorSender(sender), stream)
+
+  // Compact, the details are in the log.
+  fn block(provider: &dyn Provider, error: &BlockUpdateError) -> Block {
+    let label = provider.label();
+    let kind = Self::kind(error);
+    Block::new(color(markup::Markup::new().text(format!("⚠ {label} ({kind})")), Color::Red))
+      .short_text(color(markup::Markup::new().text(format!("⚠ {label}")), Color::Red))
+      .urgent(true)
+      .name(provider.name())
+  }
 }
 
-async fn prometheus<'b, const POINTS: usize>(executor: &'b smol::Executor<'static>, blocks: &'b BlocksGuard) -> BlockUpdateStream<'b> {
-  async_stream::stream! {
+// Sparklines always have the same width, missing points (when there isn't enough history yet) are
+// zeroes.
+fn pad(points: usize, values: &[f64]) -> Vec<f64> {
+  std::iter::once(0.)
+    .cycle()
+    .take(points.saturating_sub(values.len()))
+    .chain(values[values.len().saturating_sub(points)..].iter().copied())
+    .collect()
+}
+
+// Periodically runs a range query and renders the last points of each of its series.
+fn prometheus_range<'b, Q, F, R>(
+  executor: &'b smol::Executor<'static>,
+  slot: &'b Slot,
+  points: usize,
+  query: Q,
+  render: R,
+) -> BlockUpdateStream<'b>
+where
+  Q: Fn(&'b smol::Executor<'static>, chrono::DateTime<chrono::offset::Local>, chrono::DateTime<chrono::offset::Local>) -> F + 'b,
+  F: std::future::Future<Output = Result<Vec<prometheus::MatrixResult>, prometheus::Error>> + 'b,
+  R: Fn(&prometheus::Metric, &[f64]) -> Block + 'b,
+{
+  // Like the other fallible streams, it ends right after an error (the query is retried once it's
+  // rebuilt).
+  async_stream::try_stream! {
   let mut timer = smol::Timer::interval(std::time::Duration::from_secs(30));
+  let range = u32::try_from(points - 1)
+    .ok()
+    .and_then(|points| prometheus::STEP.checked_mul(points))
+    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("too many points ({points})")))?;
   loop {
     let end = chrono::offset::Local::now();
-    let start = end
-      - (<usize as TryInto<u32>>::try_into(POINTS - 1).unwrap() // Unwrap: ack.
-        * prometheus::STEP);
-    // All the calls could be made in parallel with a type wrapper and
-    // futures_util::future::try_join_all but latency or fine error handling doesn't matter much
-    // here.
-    yield Ok::<
-      (
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::VectorResult>,
-      ),
-      prometheus::Error,
-    >((
-      prometheus::cpu(executor, start, end).await?,
-      prometheus::download(executor, start, end).await?,
-      prometheus::temperature(executor, start, end).await?,
-      prometheus::upload(executor, start, end).await?,
-      prometheus::wifi(executor, end).await?,
-    ));
+    let start = end - range;
+    yield query(executor, start, end).await?;
 
     timer.next().await;
   }
   }
-  .map(
-    move |statuses: Result<
-      (
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::MatrixResult>,
-        Vec<prometheus::VectorResult>,
+  .map(move |matrix: Result<_, prometheus::Error>| {
+    let (range, update) = match matrix {
+      Ok(matrix) => (
+        matrix
+          .iter()
+          .filter(|result| !result.values.is_empty())
+          .map(|result| {
+            let values = result
+              .values
+              .iter()
+              .map(|prometheus::Value(_, value)| value.parse().unwrap_or(0.))
+              .collect::<Vec<_>>();
+            render(&result.metric, &pad(points, &values))
+          })
+          .collect(),
+        BlockUpdate::Publish,
       ),
-      _,
-    >| {
-      let (cpu, download, temperature, upload, wifi, update) = match statuses {
-        Ok((cpu, download, temperature, upload, wifi)) => {
-          let pad = |matrix: Vec<prometheus::MatrixResult>, default| -> Option<[f64; POINTS]> {
-            let matrix = matrix
-              .first() // It's assumed there's only one metric.
-              .map(|result| &result.values[..])
-              .unwrap_or(&[]);
-            if matrix.is_empty() {
-              return None;
-            }
-            let mut padded = std::iter::once((0, default)).cycle().take(POINTS - matrix.len()).chain(
-              matrix
+      Err(error) => (Vec::new(), BlockUpdate::Error(error.into())),
+    };
+    slot.borrow_mut(|blocks| *blocks = range);
+    update
+  })
+  .boxed_local()
+}
+
+// Renders the samples collected from /proc and /sys, like prometheus_range (there's a single series,
+// without any label).
+fn native_range<'b, R>(slot: &'b Slot, points: usize, metric: native::Metric, render: R) -> BlockUpdateStream<'b>
+where
+  R: Fn(&prometheus::Metric, &[f64]) -> Block + 'b,
+{
+  native::samples(metric, points)
+    .map(move |samples| {
+      let (range, update) = match samples {
+        Ok(samples) => (
+          vec![render(&prometheus::Metric::new(), &pad(points, &samples))],
+          BlockUpdate::Publish,
+        ),
+        Err(error) => (Vec::new(), BlockUpdate::Error(error.into())),
+      };
+      slot.borrow_mut(|blocks| *blocks = range);
+      update
+    })
+    .boxed_local()
+}
+
+struct Cpu {
+  client: std::rc::Rc<prometheus::Client>,
+  backend: config::Backend,
+  points: usize,
+  thresholds: config::Thresholds,
+}
+
+impl Provider for Cpu {
+  fn name(&self) -> &str {
+    "cpu"
+  }
+
+  fn label(&self) -> &str {
+    "CPU"
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(async move {
+      let render = move |_: &prometheus::Metric, cpu: &[f64]| {
+        Block::new(
+          cpu
+            .iter()
+            .map(|utilization| {
+              color(
+                markup::Markup::new().text(bars0(0., 1., *utilization)),
+                rising(&self.thresholds, *utilization),
+              )
+            })
+            .collect::<markup::Markup>()
+            .text(format!(" {:.00}% CPU", cpu[cpu.len() - 1] * 100.)),
+        )
+        .min_width(sparkline_width(self.points, " 100% CPU"), Align::Right)
+        .name(self.name())
+      };
+      match self.backend {
+        config::Backend::Prometheus => prometheus_range(
+          executor,
+          slot,
+          self.points,
+          |executor, start, end| prometheus::cpu(&self.client, executor, start, end),
+          render,
+        ),
+        config::Backend::Native => native_range(slot, self.points, native::Metric::Cpu, render),
+      }
+    })
+  }
+}
+
+struct Download {
+  client: std::rc::Rc<prometheus::Client>,
+  backend: config::Backend,
+  points: usize,
+  device: String,
+  maximum: f64,
+}
+
+impl Provider for Download {
+  fn name(&self) -> &str {
+    "download"
+  }
+
+  fn label(&self) -> &str {
+    "Download"
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(async move {
+      let render = move |_: &prometheus::Metric, download: &[f64]| {
+        Block::new(markup::Markup::new().text(format!(
+          "{} {:.02}M Download",
+          download.iter().map(|bytes| bars0(0., self.maximum, *bytes)).collect::<String>(),
+          download[download.len() - 1] / 1_000_000.
+        )))
+        .min_width(sparkline_width(self.points, " 99.99M Download"), Align::Right)
+        .name(self.name())
+      };
+      match self.backend {
+        config::Backend::Prometheus => prometheus_range(
+          executor,
+          slot,
+          self.points,
+          |executor, start, end| prometheus::download(&self.client, executor, &self.device, start, end),
+          render,
+        ),
+        config::Backend::Native => native_range(slot, self.points, native::Metric::Receive(self.device.clone()), render),
+      }
+    })
+  }
+}
+
+struct Promql {
+  client: std::rc::Rc<prometheus::Client>,
+  points: usize,
+  label: String,
+  query: String,
+  mode: config::Mode,
+  unit: String,
+  precision: usize,
+  minimum: f64,
+  maximum: f64,
+  thresholds: Option<config::Thresholds>,
+  series: Option<String>,
+}
+
+impl Promql {
+  fn color(&self, value: f64) -> Color {
+    match self.thresholds {
+      Some(thresholds) if thresholds.orange <= thresholds.red => rising(&thresholds, value),
+      Some(thresholds) => falling(&thresholds, value),
+      None => Color::Unspecified,
+    }
+  }
+
+  // The bars are followed by the last value and what identifies the series, if anything.
+  fn block(&self, metric: &prometheus::Metric, bars: markup::Markup, value: f64) -> Block {
+    let series = self.series.as_ref().and_then(|label| metric.get(label));
+    let mut text = format!(" {value:.*}{} {}", self.precision, self.unit, self.label);
+    if let Some(series) = series {
+      text.push_str(&format!(" {series}"));
+    }
+    let block = Block::new(bars.text(text)).name(self.name());
+    match series {
+      Some(series) => block.instance(series),
+      None => block,
+    }
+  }
+}
+
+impl Provider for Promql {
+  fn name(&self) -> &str {
+    "promql"
+  }
+
+  fn label(&self) -> &str {
+    &self.label
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(async move {
+      match self.mode {
+        config::Mode::Instant => promql_instant(self, executor, slot),
+        config::Mode::Range => prometheus_range(
+          executor,
+          slot,
+          self.points,
+          |executor, start, end| prometheus::custom_range(&self.client, executor, &self.query, start, end),
+          |metric, values| {
+            self.block(
+              metric,
+              values
                 .iter()
-                .map(|prometheus::Value(timestamp, value)| (*timestamp, value.parse().unwrap_or(default))),
-            );
-            Some(std::array::from_fn(|_| padded.next().unwrap().1)) // Unwrap: padded iterator.
-          };
-          let cpu = pad(cpu, 0.).map(|cpu| {
-            Block::new(&format!(
-              "{} {:.00}% CPU",
-              cpu
-                .map(|utilization| {
-                  color(
-                    bars0(0., 1., utilization),
-                    match utilization {
-                      utilization if utilization >= 0.7 => Color::Red,
-                      utilization if utilization >= 0.3 => Color::Orange,
-                      _ => Color::Unspecified,
-                    },
-                  )
-                })
-                .join(""),
-              cpu[cpu.len() - 1] * 100.
-            ))
-          });
-          let download = pad(download, 0.).map(|download| {
-            Block::new(&format!(
-              "{} {:.02}M Download",
-              // 1M is interesting but not too large.
-              download.map(|bytes| bars0(0., 1_000_000., bytes)).iter().collect::<String>(),
-              download[download.len() - 1] / 1_000_000.
-            ))
-          });
-          let temperature = pad(temperature, 0.).map(|temperature| {
-            Block::new(&format!(
-              "{} {:.00}°C",
-              temperature
-                .map(|degrees| {
+                .map(|value| {
                   color(
-                    bars0(30., 100., degrees), // Unlikely to be less than 30°C.
-                    match degrees {
-                      degrees if degrees >= 70. => Color::Red,
-                      degrees if degrees >= 50. => Color::Orange,
-                      _ => Color::Unspecified,
-                    },
+                    markup::Markup::new().text(bars0(self.minimum, self.maximum, *value)),
+                    self.color(*value),
                   )
                 })
-                .join(""),
-              temperature[temperature.len() - 1]
-            ))
-          });
-          let upload = pad(upload, 0.).map(|upload| {
-            Block::new(&format!(
-              "{} {:.02}M Upload",
-              // 1M is interesting but not too large.
-              upload.map(|bytes| bars0(0., 1_000_000., bytes)).iter().collect::<String>(),
-              upload[upload.len() - 1] / 1_000_000.
-            ))
-          });
-          let wifi = wifi
-            .first() // It's assumed there's only one metric.
-            .and_then(|result| {
-              // TODO: device specific
-              //  https://github.com/bmegli/wifi-scan/issues/18
-              //  https://www.intuitibits.com/2016/03/23/dbm-to-percent-conversion/
-              let signal = interpolate(-110., -40., result.value.1.parse().ok()?);
-              let bar = color(
-                bars0(0., 1., signal),
-                match signal {
-                  signal if signal <= 0.3 => Color::Red,
-                  signal if signal <= 0.5 => Color::Orange,
-                  _ => Color::Unspecified,
-                },
-              );
-              let ssid = result.metric.get("ssid")?;
-              Some(Block::new(&format!("{bar} {ssid}")))
-            });
-          (cpu, download, temperature, upload, wifi, BlockUpdate::Publish)
-        }
-        Err(error) => (None, None, None, None, None, BlockUpdate::Error(error.into())),
-      };
-      blocks.borrow_mut(|blocks| {
-        blocks.cpu = cpu;
-        blocks.download = download;
-        blocks.temperature = temperature;
-        blocks.upload = upload;
-        blocks.wifi = wifi;
-      });
-      update
-    },
-  )
+                .collect(),
+              values[values.len() - 1],
+            )
+          },
+        ),
+      }
+    })
+  }
+}
+
+fn promql_instant<'b>(promql: &'b Promql, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  // Ends right after an error, like prometheus_range.
+  async_stream::try_stream! {
+  let mut timer = smol::Timer::interval(std::time::Duration::from_secs(30));
+  loop {
+    yield prometheus::custom_instant(&promql.client, executor, &promql.query, chrono::offset::Local::now()).await?;
+
+    timer.next().await;
+  }
+  }
+  .map(move |instant: Result<_, prometheus::Error>| {
+    let (instant, update) = match instant {
+      Ok(prometheus::Instant::String(string)) => (
+        vec![Block::new(markup::Markup::new().text(format!("{string} {}", promql.label))).name(promql.name())],
+        BlockUpdate::Publish,
+      ),
+      Ok(prometheus::Instant::Vector(vector)) => (
+        vector
+          .iter()
+          .filter_map(|result| {
+            let value = result.value.1.parse().ok()?;
+            let bar = color(
+              markup::Markup::new().text(bars0(promql.minimum, promql.maximum, value)),
+              promql.color(value),
+            );
+            Some(promql.block(&result.metric, bar, value))
+          })
+          .collect(),
+        BlockUpdate::Publish,
+      ),
+      Err(error) => (Vec::new(), BlockUpdate::Error(error.into())),
+    };
+    slot.borrow_mut(|blocks| *blocks = instant);
+    update
+  })
   .boxed_local()
 }
 
-async fn upower(blocks: &BlocksGuard) -> BlockUpdateStream<'_> {
-  dbus::upower::statuses()
+struct Temperature {
+  client: std::rc::Rc<prometheus::Client>,
+  backend: config::Backend,
+  points: usize,
+  minimum: f64,
+  maximum: f64,
+  thresholds: config::Thresholds,
+}
+
+impl Provider for Temperature {
+  fn name(&self) -> &str {
+    "temperature"
+  }
+
+  fn label(&self) -> &str {
+    "Temperature"
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(async move {
+      let render = move |_: &prometheus::Metric, temperature: &[f64]| {
+        Block::new(
+          temperature
+            .iter()
+            .map(|degrees| {
+              color(
+                markup::Markup::new().text(bars0(self.minimum, self.maximum, *degrees)),
+                rising(&self.thresholds, *degrees),
+              )
+            })
+            .collect::<markup::Markup>()
+            .text(format!(" {:.00}°C", temperature[temperature.len() - 1])),
+        )
+        .min_width(sparkline_width(self.points, " 100°C"), Align::Right)
+        .name(self.name())
+      };
+      match self.backend {
+        config::Backend::Prometheus => prometheus_range(
+          executor,
+          slot,
+          self.points,
+          |executor, start, end| prometheus::temperature(&self.client, executor, start, end),
+          render,
+        ),
+        config::Backend::Native => native_range(slot, self.points, native::Metric::Temperature, render),
+      }
+    })
+  }
+}
+
+struct Upload {
+  client: std::rc::Rc<prometheus::Client>,
+  backend: config::Backend,
+  points: usize,
+  device: String,
+  maximum: f64,
+}
+
+impl Provider for Upload {
+  fn name(&self) -> &str {
+    "upload"
+  }
+
+  fn label(&self) -> &str {
+    "Upload"
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(async move {
+      let render = move |_: &prometheus::Metric, upload: &[f64]| {
+        Block::new(markup::Markup::new().text(format!(
+          "{} {:.02}M Upload",
+          upload.iter().map(|bytes| bars0(0., self.maximum, *bytes)).collect::<String>(),
+          upload[upload.len() - 1] / 1_000_000.
+        )))
+        .min_width(sparkline_width(self.points, " 99.99M Upload"), Align::Right)
+        .name(self.name())
+      };
+      match self.backend {
+        config::Backend::Prometheus => prometheus_range(
+          executor,
+          slot,
+          self.points,
+          |executor, start, end| prometheus::upload(&self.client, executor, &self.device, start, end),
+          render,
+        ),
+        config::Backend::Native => native_range(slot, self.points, native::Metric::Transmit(self.device.clone()), render),
+      }
+    })
+  }
+}
+
+struct Upower {
+  thresholds: config::Thresholds,
+  composite: bool,
+  notifier: Notifier,
+}
+
+// Sends the low battery notifications, on the executor: a slow notification daemon mustn't stall
+// the updates.
+#[derive(Clone)]
+struct Notifier {
+  percentages: std::sync::Arc<[f64]>,
+  // Per device, the lowest percentage notified and the notification's id. The lock is held for a
+  // whole update, so a notification is sent before it's replaced or closed.
+  notified: std::sync::Arc<smol::lock::Mutex<std::collections::HashMap<zvariant::OwnedObjectPath, (f64, u32)>>>,
+}
+
+impl Notifier {
+  fn new(percentages: &[f64]) -> Self {
+    Self {
+      percentages: percentages.into(),
+      notified: std::sync::Arc::new(smol::lock::Mutex::new(std::collections::HashMap::new())),
+    }
+  }
+
+  // A notification is sent once per crossed percentage while on battery, until the battery
+  // recovers or the system is plugged in (the notification is closed then).
+  async fn notify(self, statuses: Vec<dbus::upower::Status>) {
+    let mut notified = self.notified.lock().await;
+    for status in statuses {
+      let crossed = self
+        .percentages
+        .iter()
+        .copied()
+        .filter(|percentage| status.percentage <= *percentage)
+        .min_by(f64::total_cmp);
+      match (notified.get(&status.path).copied(), crossed) {
+        (Some((_, id)), crossed) if !status.on_battery || crossed.is_none() => {
+          notified.remove(&status.path);
+          if let Err(error) = dbus::notifications::close(id).await {
+            log::warn!("Failed to close notification {id}: {error:?}");
+          }
+        }
+        (None, Some(crossed)) if status.on_battery => Self::notify_crossed(&mut notified, &status, crossed, 0).await,
+        (Some((previous, id)), Some(crossed)) if status.on_battery && crossed < previous => {
+          Self::notify_crossed(&mut notified, &status, crossed, id).await
+        }
+        _ => {}
+      }
+    }
+  }
+
+  async fn notify_crossed(
+    notified: &mut std::collections::HashMap<zvariant::OwnedObjectPath, (f64, u32)>,
+    status: &dbus::upower::Status,
+    crossed: f64,
+    replaces: u32,
+  ) {
+    let device = match status.device.as_str() {
+      "" => "Battery", // The DisplayDevice.
+      device => device,
+    };
+    let mut body = format!("{device} is at {:.00}%", status.percentage);
+    if let Some(time_to_empty) = status.time_to_empty {
+      body.push_str(&format!(", {} remaining", remaining(time_to_empty)));
+    }
+    match dbus::notifications::critical("Low battery", &body, replaces).await {
+      Ok(id) => _ = notified.insert(status.path.clone(), (crossed, id)),
+      Err(error) => log::warn!("Failed to notify: {error:?}"),
+    }
+  }
+}
+
+impl Provider for Upower {
+  fn name(&self) -> &str {
+    "upower"
+  }
+
+  fn label(&self) -> &str {
+    "Battery"
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(upower(self, executor, slot))
+  }
+}
+
+// As H:MM, minutes are precise enough for an estimate.
+fn remaining(duration: std::time::Duration) -> String {
+  let minutes = duration.as_secs() / 60;
+  format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+async fn upower<'b>(provider: &'b Upower, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  dbus::upower::statuses(provider.composite)
     .await
-    .map(|statuses| {
+    .inspect(move |statuses| {
+      if let Ok(statuses) = statuses {
+        executor.spawn(provider.notifier.clone().notify(statuses.clone())).detach();
+      }
+    })
+    .map(move |statuses| {
       let (upower, update) = match statuses {
-        Ok(statuses) => {
-          (
-            statuses
-              .iter()
-              .map(|dbus::upower::Status { device, percentage }| {
+        Ok(statuses) => (
+          statuses
+            .iter()
+            .map(
+              |dbus::upower::Status {
+                 device,
+                 percentage,
+                 state,
+                 time_to_empty,
+                 time_to_full,
+                 energy_rate,
+                 warning_level,
+                 ..
+               }| {
                 let bar = bars1(0., 100., *percentage); // The battery can't really reach 0.
-                let bar = color(
-                  bar,
-                  match *percentage {
-                    percentage if percentage <= 10. => Color::Red,
-                    percentage if percentage <= 30. => Color::Orange,
-                    _ => Color::Unspecified,
-                  },
-                );
-                Block::new(&format!("{bar} {percentage:.00}% {device}"))
-              })
-              .collect(),
-            BlockUpdate::Publish,
-          )
-        }
+                let level = falling(&provider.thresholds, *percentage);
+                // UPower may know better (e.g.: it's about to hibernate).
+                let urgent = matches!(level, Color::Red) || *warning_level >= dbus::upower::WarningLevel::Critical;
+                let (glyph, remaining) = match state {
+                  dbus::upower::State::Charging | dbus::upower::State::PendingCharge => (" ▲", time_to_full.map(remaining)),
+                  dbus::upower::State::Discharging | dbus::upower::State::PendingDischarge => (" ▼", time_to_empty.map(remaining)),
+                  _ => ("", None),
+                };
+                let mut short_text = color(markup::Markup::new().text(bar), level).text(format!(" {percentage:.00}%{glyph}"));
+                if let Some(remaining) = remaining {
+                  short_text = short_text.text(format!(" {remaining}"));
+                }
+                let mut full_text = short_text.clone();
+                if *energy_rate > 0. {
+                  full_text = full_text.text(format!(" {energy_rate:.1} W"));
+                }
+                // The DisplayDevice has no model.
+                if !device.is_empty() {
+                  full_text = full_text.text(format!(" {device}"));
+                }
+                Block::new(full_text).short_text(short_text).urgent(urgent).name(provider.name())
+              },
+            )
+            .collect(),
+          BlockUpdate::Publish,
+        ),
         Err(error) => (Vec::new(), BlockUpdate::Error(error.into())),
       };
-      blocks.borrow_mut(|blocks| blocks.upower = upower);
+      slot.borrow_mut(|blocks| *blocks = upower);
       update
     })
     .boxed_local()
 }
 
-async fn volume(blocks: &BlocksGuard) -> BlockUpdateStream<'_> {
-  volume::statuses()
+// Both the output (volume) and the input (microphone) blocks.
+struct Volume {
+  kind: volume::Kind,
+  step: u8,
+  maximum: u16,
+}
+
+impl Provider for Volume {
+  fn name(&self) -> &str {
+    match self.kind {
+      volume::Kind::Sink => "volume",
+      volume::Kind::Source => "microphone",
+    }
+  }
+
+  fn label(&self) -> &str {
+    match self.kind {
+      volume::Kind::Sink => "Volume",
+      volume::Kind::Source => "Microphone",
+    }
+  }
+
+  fn stream<'b>(&'b self, _executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    Box::pin(volume(self, slot))
+  }
+
+  fn click<'b>(&'b self, Click { button, .. }: &'b Click) -> Option<LocalFuture<'b, Result<(), BlockUpdateError>>> {
+    match button {
+      1 => Some(Box::pin(async { Ok(volume::mute(self.kind).await?) })),
+      3 => Some(Box::pin(async { Ok(volume::next(self.kind).await?) })),
+      4 => Some(Box::pin(async { Ok(volume::up(self.kind, self.step, self.maximum).await?) })),
+      5 => Some(Box::pin(async { Ok(volume::down(self.kind, self.step, self.maximum).await?) })),
+      _ => None,
+    }
+  }
+}
+
+async fn volume<'b>(provider: &'b Volume, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  volume::statuses(provider.kind)
     .await
-    .map(|status| {
+    .map(move |status| {
       let (volume, update) = match status {
         Ok(status) => (
-          Some(
-            Block::new(&format!(
-              "{} Volume",
-              bars0(
-                0.,
-                100.,
-                match status {
-                  volume::Status::Mute => 0.,
-                  volume::Status::Volume(volume) => volume.into(),
+          status
+            .map(
+              |volume::Status {
+                 device,
+                 mute,
+                 volume,
+                 recording,
+               }| {
+                let bar = bars0(0., 100., if mute { 0. } else { volume.into() });
+                match provider.kind {
+                  volume::Kind::Sink => Block::new(markup::Markup::new().text(format!("{bar} {device}")))
+                    .short_text(markup::Markup::new().text(format!("{bar} Volume"))),
+                  // A live microphone stands out, even more so when something records.
+                  volume::Kind::Source => {
+                    let level = if mute { Color::Unspecified } else { Color::Red };
+                    let indicator = if recording { "● " } else { "" };
+                    Block::new(color(markup::Markup::new().text(format!("{indicator}{bar} {device}")), level))
+                      .short_text(color(markup::Markup::new().text(format!("{indicator}{bar} Microphone")), level))
+                  }
                 }
-              )
-            ))
-            .name("volume"),
+                .name(provider.name())
+              },
+            )
+            .into_iter()
+            .collect(),
+          BlockUpdate::Publish,
+        ),
+        Err(error) => (Vec::new(), BlockUpdate::Error(error.into())),
+      };
+      slot.borrow_mut(|blocks| *blocks = volume);
+      update
+    })
+    .boxed_local()
+}
+
+struct Wifi {
+  client: std::rc::Rc<prometheus::Client>,
+  backend: config::Backend,
+  interface: Option<String>,
+  minimum: f64,
+  maximum: f64,
+  thresholds: config::Thresholds,
+}
+
+impl Wifi {
+  // The signal is in dBm, the details are only shown when there's enough room.
+  fn block(&self, signal: f64, ssid: &str, details: &str) -> Block {
+    let signal = interpolate(self.minimum, self.maximum, signal);
+    let bar = color(markup::Markup::new().text(bars0(0., 1., signal)), falling(&self.thresholds, signal));
+    Block::new(bar.clone().text(format!(" {ssid}{details}")))
+      .short_text(bar)
+      .name(self.name())
+  }
+}
+
+impl Provider for Wifi {
+  fn name(&self) -> &str {
+    "wifi"
+  }
+
+  fn label(&self) -> &str {
+    "Wi-Fi"
+  }
+
+  fn stream<'b>(&'b self, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+    match self.backend {
+      config::Backend::Prometheus => Box::pin(wifi_prometheus(self, executor, slot)),
+      config::Backend::Native => Box::pin(wifi_native(self, slot)),
+    }
+  }
+}
+
+async fn wifi_prometheus<'b>(wifi: &'b Wifi, executor: &'b smol::Executor<'static>, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  // Ends right after an error, like prometheus_range.
+  async_stream::try_stream! {
+  let mut timer = smol::Timer::interval(std::time::Duration::from_secs(30));
+  loop {
+    yield prometheus::wifi(&wifi.client, executor, chrono::offset::Local::now()).await?;
+
+    timer.next().await;
+  }
+  }
+  .map(move |vector: Result<_, prometheus::Error>| {
+    let (block, update) = match vector {
+      Ok(vector) => (
+        vector
+          .first() // It's assumed there's only one metric.
+          .and_then(|result| Some(wifi.block(result.value.1.parse().ok()?, result.metric.get("ssid")?, ""))),
+        BlockUpdate::Publish,
+      ),
+      Err(error) => (None, BlockUpdate::Error(error.into())),
+    };
+    slot.borrow_mut(|blocks| *blocks = block.into_iter().collect());
+    update
+  })
+  .boxed_local()
+}
+
+async fn wifi_native<'b>(wifi: &'b Wifi, slot: &'b Slot) -> BlockUpdateStream<'b> {
+  nl80211::statuses(wifi.interface.clone())
+    .await
+    .map(move |status| {
+      let (block, update) = match status {
+        Ok(status) => (
+          status.map(
+            |nl80211::Status {
+               interface,
+               ssid,
+               signal,
+               bitrate,
+               frequency,
+             }| {
+              let mut details = String::new();
+              if let Some(frequency) = frequency {
+                details.push_str(&format!(" {:.1}GHz", f64::from(frequency) / 1000.));
+              }
+              if let Some(bitrate) = bitrate {
+                details.push_str(&format!(" {bitrate:.0}Mb/s"));
+              }
+              wifi.block(signal, ssid.as_ref().unwrap_or(&interface), &details)
+            },
           ),
           BlockUpdate::Publish,
         ),
         Err(error) => (None, BlockUpdate::Error(error.into())),
       };
-      blocks.borrow_mut(|blocks| blocks.volume = volume);
+      slot.borrow_mut(|blocks| *blocks = block.into_iter().collect());
       update
     })
     .boxed_local()
 }
 
+// The providers, in the order their blocks are displayed.
+fn registry(config: &config::Config, client: std::rc::Rc<prometheus::Client>) -> Vec<Box<dyn Provider>> {
+  let points = config.prometheus.points;
+  config
+    .blocks
+    .iter()
+    .map(|block| -> Box<dyn Provider> {
+      match *block {
+        config::Block::Bluez { thresholds } => Box::new(Bluez::new(thresholds)),
+        config::Block::Clock {
+          ref formats,
+          ref zones,
+          ref locale,
+          seconds,
+        } => Box::new(Clock {
+          formats: formats.clone(),
+          zones: zones.clone(),
+          locale: clock::locale(locale.as_deref()),
+          seconds,
+          format: std::cell::Cell::new(0),
+          clicked: async_channel::bounded(1),
+        }),
+        config::Block::Cpu { backend, thresholds } => Box::new(Cpu {
+          client: client.clone(),
+          backend,
+          points,
+          thresholds,
+        }),
+        config::Block::Download {
+          backend,
+          ref device,
+          maximum,
+        } => Box::new(Download {
+          client: client.clone(),
+          backend,
+          points,
+          device: device.clone(),
+          maximum,
+        }),
+        config::Block::Promql {
+          ref label,
+          ref query,
+          mode,
+          ref unit,
+          precision,
+          minimum,
+          maximum,
+          thresholds,
+          ref series,
+        } => Box::new(Promql {
+          client: client.clone(),
+          points,
+          label: label.clone(),
+          query: query.clone(),
+          mode,
+          unit: unit.clone(),
+          precision,
+          minimum,
+          maximum,
+          thresholds,
+          series: series.clone(),
+        }),
+        config::Block::Temperature {
+          backend,
+          minimum,
+          maximum,
+          thresholds,
+        } => Box::new(Temperature {
+          client: client.clone(),
+          backend,
+          points,
+          minimum,
+          maximum,
+          thresholds,
+        }),
+        config::Block::Upload {
+          backend,
+          ref device,
+          maximum,
+        } => Box::new(Upload {
+          client: client.clone(),
+          backend,
+          points,
+          device: device.clone(),
+          maximum,
+        }),
+        config::Block::Upower {
+          thresholds,
+          composite,
+          ref notifications,
+        } => Box::new(Upower {
+          thresholds,
+          composite,
+          notifier: Notifier::new(notifications),
+        }),
+        config::Block::Microphone { step, maximum } => Box::new(Volume {
+          kind: volume::Kind::Source,
+          step,
+          maximum,
+        }),
+        config::Block::Volume { step, maximum } => Box::new(Volume {
+          kind: volume::Kind::Sink,
+          step,
+          maximum,
+        }),
+        config::Block::Wifi {
+          backend,
+          ref interface,
+          minimum,
+          maximum,
+          thresholds,
+        } => Box::new(Wifi {
+          client: client.clone(),
+          backend,
+          interface: interface.clone(),
+          minimum,
+          maximum,
+          thresholds,
+        }),
+      }
+    })
+    .collect()
+}
+
 fn main() -> std::io::Result<()> {
   // Technically, stderr could block... For simplicity's sake (and because I don't want to roll my
   // own logging framework), let's ignore it.
   env_logger::init();
 
+  let config = match config::Config::load() {
+    Ok(config) => config,
+    Err(error) => {
+      log::error!("Failed to load the configuration: {error}");
+      std::process::exit(1);
+    }
+  };
+  // Shared by the Prometheus providers.
+  let client = match prometheus::Client::new(&config.prometheus) {
+    Ok(client) => std::rc::Rc::new(client),
+    Err(error) => {
+      log::error!("Failed to create the Prometheus client: {error:?}");
+      std::process::exit(1);
+    }
+  };
+  let registry = registry(&config, client.clone());
+  let error_count = config.errors.count;
+
   let executor = smol::Executor::new();
   smol::block_on(executor.run(async {
+    // The handlers must be registered before advertising the signals.
+    let signals = signals(async_signal::Signals::new([STOP_SIGNAL, CONT_SIGNAL])?);
+
     let mut stdout = smol::Unblock::new(std::io::stdout());
     stdout
       .write_all(&serde_json::to_vec(&serde_json::json!({
         "version": 1,
         "click_events": true,
+        "stop_signal": STOP_SIGNAL as i32,
+        "cont_signal": CONT_SIGNAL as i32,
       }))?)
       .await?;
     stdout.write_all(b"\n[").await?;
 
-    let blocks = BlocksGuard::new(Blocks::default());
-
-    let (error_sender, error) = error(&blocks).await;
-    let mut infallible_streams = [clock(&blocks).await, error];
-    let fallible_futures: &[Box<
-      dyn for<'b> Fn(
-        &'b smol::Executor<'static>,
-        &'b BlocksGuard,
-      ) -> std::pin::Pin<Box<dyn std::future::Future<Output = BlockUpdateStream<'b>> + 'b>>,
-    >] = &[
-      Box::new(|_, blocks| Box::pin(bluez(blocks))),
-      Box::new(|_, _| Box::pin(clicks())),
-      Box::new(|executor, blocks| Box::pin(prometheus::<5>(executor, blocks))),
-      Box::new(|_, blocks| Box::pin(upower(blocks))),
-      Box::new(|_, blocks| Box::pin(volume(blocks))),
-    ];
-    let mut fallible_streams = futures_util::future::join_all(fallible_futures.iter().map(|future| future(&executor, &blocks))).await;
+    let slots = registry.iter().map(|_| Slot::new(Vec::new())).collect::<Vec<_>>();
+    let mut failures: Vec<Option<Failure>> = vec![None; registry.len()];
+
+    let (backoff_sender, backoff) = backoff();
+    let mut infallible_streams = [backoff, signals, sleeps()];
+    // Clicks aren't tied to a provider but their stream is rebuilt the same way.
+    let fallible_futures =
+      std::iter::once(Box::new(|| -> LocalFuture<'_, BlockUpdateStream<'_>> { Box::pin(clicks()) }) as Box<dyn Fn() -> _>)
+        .chain(
+          registry
+            .iter()
+            .zip(&slots)
+            .map(|(provider, slot)| Box::new(|| provider.stream(&executor, slot)) as Box<dyn Fn() -> _>),
+        )
+        .collect::<Vec<_>>();
+    let mut fallible_streams = futures_util::future::join_all(fallible_futures.iter().map(|future| future())).await;
     // It doesn't seem possible to use a single pending stream or future.
     let mut pending_streams = (0..fallible_streams.len())
       .map(|_| smol::stream::pending().boxed_local())
       .collect::<Vec<_>>();
     let mut failed_streams = vec![false; fallible_streams.len()];
+    // While the bar is hidden or the system sleeps, the providers' streams are dropped: nothing is
+    // polled (no timer, no D-Bus or pactl events) and nothing is published.
+    let (mut hidden, mut sleeping) = (false, false);
+    let mut paused = false;
+    // Bursts of publishes (e.g.: BlueZ or UPower firing many PropertiesChanged signals in a row)
+    // are coalesced: the bar is written once the window following the first publish is over, and
+    // only if it changed since the last write (sway's CPU usage spikes a bit on each line).
+    let mut publish: Option<smol::Timer> = None;
+    let mut published = Vec::new();
+    let (mut coalesced, mut duplicates) = (0u64, 0u64);
+
+    // Clicks are handled while the bar keeps going (e.g.: connecting to an unreachable Bluetooth
+    // device takes a while).
+    let mut clicks = futures_util::stream::FuturesUnordered::new();
+
+    enum Event {
+      Refresh(Option<BlockUpdate>, usize),
+      // With the index of the provider.
+      Clicked(usize, Result<(), BlockUpdateError>),
+      Publish,
+    }
 
     loop {
-      let (refresh, index, _) = futures_util::future::select_all(
-        fallible_streams
-          .iter_mut()
-          .chain(infallible_streams.iter_mut())
-          .map(|stream| stream.next()),
+      let event = smol::future::or(
+        async {
+          let (refresh, index, _) = futures_util::future::select_all(
+            fallible_streams
+              .iter_mut()
+              .chain(infallible_streams.iter_mut())
+              .map(|stream| stream.next()),
+          )
+          .await;
+          Event::Refresh(refresh, index)
+        },
+        smol::future::or(
+          async {
+            match clicks.is_empty() {
+              // Unwrap: there's at least one future.
+              false => {
+                let (provider, result) = clicks.next().await.unwrap();
+                Event::Clicked(provider, result)
+              }
+              true => smol::future::pending().await,
+            }
+          },
+          async {
+            match publish.as_mut() {
+              Some(timer) => {
+                timer.await;
+                Event::Publish
+              }
+              None => smol::future::pending().await,
+            }
+          },
+        ),
       )
       .await;
+      let (refresh, index) = match event {
+        Event::Refresh(refresh, index) => (refresh, index),
+        Event::Clicked(_, Ok(())) => continue,
+        Event::Clicked(index, Err(error)) => {
+          let provider = &registry[index];
+          log::warn!("Failed to handle a click on {}: {}", provider.name(), chain(&error));
+          log::debug!("{error:?}");
+          failures[index] = Some(Failure::Click(Failure::block(provider.as_ref(), &error)));
+          backoff_sender.try_send(()).ok(); // A full queue already has an error pending.
+          if publish.is_none() {
+            publish = Some(smol::Timer::after(PUBLISH_WINDOW));
+          }
+          continue;
+        }
+        Event::Publish => {
+          publish = None;
+          if paused {
+            continue;
+          }
+          let count = failures.iter().flatten().count();
+          let bar = serde_json::to_vec(
+            &(error_count && count > 0)
+              .then(|| Block::new(color(markup::Markup::new().text(format!("⚠ {count}")), Color::Red)).name("errors"))
+              .into_iter()
+              .chain(slots.iter().zip(&failures).enumerate().flat_map(|(index, (slot, failure))| {
+                let blocks = match failure {
+                  Some(Failure::Stream(block)) => vec![block.clone()],
+                  Some(Failure::Click(block)) => slot.borrow(|blocks| blocks.iter().chain([block]).cloned().collect()),
+                  None => slot.borrow(|blocks| blocks.clone()),
+                };
+                blocks.into_iter().map(move |block| route(index, block))
+              }))
+              .collect::<Vec<_>>(),
+          )?;
+          if bar == published {
+            duplicates += 1;
+            log::debug!("Skipped identical bar ({duplicates} so far)");
+            continue;
+          }
+          stdout.write_all(&bar).await?;
+          stdout.write_all(b",\n").await?;
+          published = bar;
+          continue;
+        }
+      };
       match refresh {
-        Some(BlockUpdate::Click(
-          ref click @ Click {
-            ref name,
-            ref instance,
-            button,
-          },
-        )) => {
+        Some(BlockUpdate::Click(click)) => {
           log::trace!("Click from stream {index:?}: {click:?}");
-          match name.as_str() {
-            "bluez" => match button {
-              // TODO: Toggling an unreachable device might block for a little while.
-              1 => match dbus::bluez::toggle(
-                instance
-                  .clone()
-                  .map(|path| {
-                    zvariant::ObjectPath::try_from(path)
-                      // Unwrap: the ObjectPath was converted to a string when declaring the block.
-                      .unwrap()
-                  })
-                  .as_ref(),
-              )
-              .await
-              {
-                Ok(()) => (),
-                Err(error) => {
-                  log::warn!("Failed to handle event: {error:?}");
-                  error_sender.force_send(error.into()).unwrap(); // Unwrap: the receiver won't close.
+          let provider =
+            routed(&click.name).and_then(|(name, index)| Some((index, registry.get(index).filter(|provider| provider.name() == name)?)));
+          match provider {
+            Some((index, provider)) => clicks.push(Box::pin(async move {
+              let result = match provider.click(&click) {
+                Some(future) => future.await,
+                None => {
+                  log::trace!("Unhandled button: {:?}", click.button);
+                  Ok(())
                 }
-              },
-              button => log::trace!("Unhandled button: {button:?}"),
-            },
-            "volume" => match match button {
-              1 => volume::mute().await,
-              4 => volume::up().await,
-              5 => volume::down().await,
-              button => {
-                log::trace!("Unhandled button: {button:?}");
-                Ok(())
-              }
-            } {
-              Ok(()) => (),
-              Err(error) => {
-                log::warn!("Failed to handle event: {error:?}");
-                error_sender.force_send(error.into()).unwrap(); // Unwrap: the receiver won't close.
-              }
-            },
-            name => log::warn!("Unhandled event: {name:?}"),
+              };
+              (index, result)
+            }) as LocalFuture<'_, _>),
+            None => log::warn!("Unhandled event: {:?}", click.name),
           }
         }
         Some(BlockUpdate::Error(error)) => {
-          // A fallible stream will end right after an error.
-          log::warn!("Error from stream {index:?}: {error:?}");
-          error_sender.force_send(error).unwrap(); // Unwrap: the receiver won't close.
+          // A provider's stream will end right after an error, it's rebuilt after the backoff period.
+          // The clicks' keeps going (a malformed line doesn't mean stdin is closed).
+          log::warn!("Error from stream {index:?}: {}", chain(&error));
+          log::debug!("{error:?}");
+          // Clicks aren't tied to a provider and infallible streams come after the providers'.
+          if let Some(provider) = index.checked_sub(1).filter(|provider| *provider < registry.len()) {
+            failures[provider] = Some(Failure::Stream(Failure::block(registry[provider].as_ref(), &error)));
+            if publish.is_none() {
+              publish = Some(smol::Timer::after(PUBLISH_WINDOW));
+            }
+          }
+          backoff_sender.try_send(()).ok(); // A full queue already has an error pending.
+        }
+        Some(update @ (BlockUpdate::Pause | BlockUpdate::Sleep)) => {
+          log::debug!("{update:?} from stream {index:?}");
+          match update {
+            BlockUpdate::Sleep => {
+              sleeping = true;
+              // Nothing can check a connection in until the streams are rebuilt after waking up.
+              client.disconnect();
+            }
+            _ => hidden = true,
+          }
+          if !paused {
+            paused = true;
+            // Skip the clicks, the bar can still be interacted with. An ended stream is forgotten too
+            // (it's rebuilt on resume like the others), or it would be swapped back in when the
+            // rebuilt one ends.
+            for index in 1..fallible_streams.len() {
+              failed_streams[index] = false;
+              fallible_streams[index] = smol::stream::pending().boxed_local();
+              pending_streams[index] = smol::stream::pending().boxed_local();
+            }
+          }
         }
+        Some(update @ (BlockUpdate::Resume | BlockUpdate::Wake)) => {
+          log::debug!("{update:?} from stream {index:?}");
+          match update {
+            BlockUpdate::Wake => sleeping = false,
+            _ => hidden = false,
+          }
+          if paused && !hidden && !sleeping {
+            paused = false;
+            // Rebuilding the streams resynchronizes the state that was missed while paused.
+            for index in 1..fallible_streams.len() {
+              fallible_streams[index] = fallible_futures[index]().await;
+            }
+          }
+        }
+        Some(BlockUpdate::Publish) if paused => log::trace!("Ignored publish from stream {index:?}"),
         Some(BlockUpdate::Publish) => {
-          // A stream published something.
+          // A stream published something, it recovered if it had failed.
           log::trace!("Publish from stream {index:?}");
-          // TODO: Avoid bursts with some caching (sway CPU usage spikes a bit).
-          let bar = blocks.borrow(
-            |Blocks {
-               bluez,
-               clock,
-               cpu,
-               download,
-               error,
-               temperature,
-               upload,
-               upower,
-               volume,
-               wifi,
-             }| {
-              error
-                .iter()
-                .chain(upload.iter())
-                .chain(download.iter())
-                .chain(wifi.iter())
-                .chain(temperature.iter())
-                .chain(cpu.iter())
-                .chain(upower.iter())
-                .chain(volume.iter())
-                .chain(bluez.iter())
-                .chain(clock.iter())
-                .cloned()
-                .collect::<Vec<_>>()
-            },
-          );
-          stdout.write_all(&serde_json::to_vec(&bar)?).await?;
-          stdout.write_all(b",\n").await?;
+          if let Some(failure @ Some(Failure::Stream(_))) = index.checked_sub(1).and_then(|provider| failures.get_mut(provider)) {
+            *failure = None;
+          }
+          match publish {
+            Some(_) => {
+              coalesced += 1;
+              log::debug!("Coalesced publish from stream {index:?} ({coalesced} so far)");
+            }
+            None => publish = Some(smol::Timer::after(PUBLISH_WINDOW)),
+          }
         }
+        Some(BlockUpdate::Rebuild) if paused => log::trace!("Ignored rebuild from stream {index:?}"),
         Some(BlockUpdate::Rebuild) => {
           // After a backoff period, an ended fallible stream is rebuilt.
           log::trace!("Rebuild from stream {index:?}");
+          for failure in &mut failures {
+            if let Some(Failure::Click(_)) = failure {
+              *failure = None;
+            }
+          }
+          if publish.is_none() {
+            publish = Some(smol::Timer::after(PUBLISH_WINDOW));
+          }
           for index in 0..fallible_streams.len() {
             if failed_streams[index] {
-              pending_streams[index] = fallible_futures[index](&executor, &blocks).await;
+              pending_streams[index] = fallible_futures[index]().await;
               failed_streams[index] = false;
               std::mem::swap(&mut fallible_streams[index], &mut pending_streams[index]);
             }
@@ -624,3 +1865,130 @@ fn main() -> std::io::Result<()> {
     }
   }))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Test;
+
+  impl Provider for Test {
+    fn name(&self) -> &str {
+      "test"
+    }
+
+    fn label(&self) -> &str {
+      "Test"
+    }
+
+    fn stream<'b>(&'b self, _executor: &'b smol::Executor<'static>, _slot: &'b Slot) -> LocalFuture<'b, BlockUpdateStream<'b>> {
+      Box::pin(async { smol::stream::empty().boxed_local() })
+    }
+  }
+
+  #[test]
+  fn prometheus_failure() {
+    let error = BlockUpdateError::Prometheus(prometheus::Error::Prometheus {
+      kind: "bad_data".to_string(),
+      message: r#"invalid parameter "query": 1:5: parse error: unexpected <EOF>"#.to_string(),
+      warnings: Vec::new(),
+    });
+    let block = Failure::block(&Test, &error);
+    assert_eq!(
+      block.full_text,
+      r#"<span color="red">⚠ Test (bad_data: invalid parameter &quot;query&quot;: 1:5: parse er…)</span>"#
+    );
+    assert_eq!(block.short_text.as_deref(), Some(r#"<span color="red">⚠ Test</span>"#));
+  }
+
+  #[test]
+  fn failure_kinds() {
+    for (error, kind) in [
+      (BlockUpdateError::Prometheus(prometheus::Error::Timeout), "timeout"),
+      (
+        BlockUpdateError::Prometheus(prometheus::Error::HTTPStatus(http::StatusCode::SERVICE_UNAVAILABLE)),
+        "HTTP 503",
+      ),
+      (
+        BlockUpdateError::Prometheus(prometheus::Error::IO(std::io::ErrorKind::ConnectionRefused.into())),
+        "connection refused",
+      ),
+      (BlockUpdateError::Bluez(dbus::bluez::Error::HardBlocked), "Hard blocked"),
+      (BlockUpdateError::Bluez(dbus::bluez::Error::Timeout), "timeout"),
+      (
+        BlockUpdateError::IO(std::io::Error::other("pulse: expected tag 'L', got 't'")),
+        "pulse: expected tag 'L', got 't'",
+      ),
+    ] {
+      assert_eq!(Failure::kind(&error), kind);
+    }
+  }
+
+  #[test]
+  fn unset_fields() {
+    assert_eq!(
+      serde_json::to_value(Block::new(markup::Markup::new().text("text"))).unwrap(),
+      serde_json::json!({ "full_text": "text", "markup": "pango" })
+    );
+  }
+
+  #[test]
+  fn set_fields() {
+    let block = Block::new(markup::Markup::new().text("full"))
+      .short_text(markup::Markup::new().text("short"))
+      .color("#ff0000")
+      .background("#00ff00")
+      .border("#0000ff80")
+      .border_top(1)
+      .border_bottom(2)
+      .border_left(3)
+      .border_right(4)
+      .min_width(MinWidth::Pixels(100), Align::Center)
+      .urgent(true)
+      .name("name")
+      .instance("instance")
+      .separator(false)
+      .separator_block_width(5);
+    assert_eq!(
+      serde_json::to_value(block).unwrap(),
+      serde_json::json!({
+        "full_text": "full",
+        "short_text": "short",
+        "color": "#ff0000",
+        "background": "#00ff00",
+        "border": "#0000ff80",
+        "border_top": 1,
+        "border_bottom": 2,
+        "border_left": 3,
+        "border_right": 4,
+        "min_width": 100,
+        "align": "center",
+        "urgent": true,
+        "name": "name",
+        "instance": "instance",
+        "separator": false,
+        "separator_block_width": 5,
+        "markup": "pango",
+      })
+    );
+  }
+
+  #[test]
+  fn min_width_text() {
+    let block = Block::new(markup::Markup::new().text("1%")).min_width(MinWidth::Text("100%".to_string()), Align::Left);
+    assert_eq!(
+      serde_json::to_value(block).unwrap(),
+      serde_json::json!({ "full_text": "1%", "min_width": "100%", "align": "left", "markup": "pango" })
+    );
+  }
+
+  #[test]
+  fn not_urgent() {
+    // Left to swaybar's default.
+    let block = Block::new(markup::Markup::new().text("text")).urgent(false);
+    assert_eq!(
+      serde_json::to_value(block).unwrap(),
+      serde_json::json!({ "full_text": "text", "markup": "pango" })
+    );
+  }
+}
@@ -0,0 +1,143 @@
+// Pango markup, as advertised by every block.
+// https://docs.gtk.org/Pango/pango_markup.html
+//
+// Text is escaped by default: SSIDs, device names, battery models, ... come from the outside and a
+// single '<' or '&' would otherwise break the whole bar (or inject markup). Tags can only be
+// produced by the methods below.
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Markup(String);
+
+// Same as g_markup_escape_text: GMarkup refuses raw control characters so they are replaced by
+// character references (except NUL, which can't be represented at all).
+fn escape(string: &mut String, text: &str) {
+  for character in text.chars() {
+    match character {
+      '&' => string.push_str("&amp;"),
+      '<' => string.push_str("&lt;"),
+      '>' => string.push_str("&gt;"),
+      '\'' => string.push_str("&apos;"),
+      '"' => string.push_str("&quot;"),
+      '\0' => string.push(char::REPLACEMENT_CHARACTER),
+      '\u{1}'..='\u{8}' | '\u{b}' | '\u{c}' | '\u{e}'..='\u{1f}' | '\u{7f}'..='\u{84}' | '\u{86}'..='\u{9f}' => {
+        string.push_str(&format!("&#x{:x};", character as u32))
+      }
+      character => string.push(character),
+    }
+  }
+}
+
+impl Markup {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Appends escaped text.
+  pub fn text<D: std::fmt::Display>(mut self, text: D) -> Self {
+    escape(&mut self.0, &text.to_string());
+    self
+  }
+
+  // Appends already built markup.
+  pub fn push(mut self, markup: Markup) -> Self {
+    self.0.push_str(&markup.0);
+    self
+  }
+
+  // Wraps the markup in a <span> with the given attributes (their values are escaped).
+  pub fn span(self, attributes: &[(&str, &str)]) -> Self {
+    let mut string = "<span".to_string();
+    for (attribute, value) in attributes {
+      string.push_str(&format!(" {attribute}=\""));
+      escape(&mut string, value);
+      string.push('"');
+    }
+    string.push('>');
+    string.push_str(&self.0);
+    string.push_str("</span>");
+    Self(string)
+  }
+
+  pub fn into_string(self) -> String {
+    self.0
+  }
+}
+
+impl FromIterator<Markup> for Markup {
+  fn from_iter<I: IntoIterator<Item = Markup>>(iterator: I) -> Self {
+    iterator.into_iter().fold(Self::new(), Self::push)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Markup;
+
+  #[test]
+  fn text() {
+    assert_eq!(
+      Markup::new().text(r#"<b>"Bob's" & Alice's</b>"#).into_string(),
+      "&lt;b&gt;&quot;Bob&apos;s&quot; &amp; Alice&apos;s&lt;/b&gt;"
+    );
+  }
+
+  #[test]
+  fn entities() {
+    // Already escaped text is escaped again, it's displayed as is.
+    assert_eq!(Markup::new().text("&amp; &#x3c;").into_string(), "&amp;amp; &amp;#x3c;");
+  }
+
+  #[test]
+  fn control_characters() {
+    assert_eq!(
+      Markup::new().text("a\u{1}b\u{1b}[31mc\u{7f}d\u{85}e\u{9f}").into_string(),
+      "a&#x1;b&#x1b;[31mc&#x7f;d\u{85}e&#x9f;"
+    );
+    // Tabs and newlines are fine.
+    assert_eq!(Markup::new().text("a\tb\nc\rd").into_string(), "a\tb\nc\rd");
+  }
+
+  #[test]
+  fn nul() {
+    assert_eq!(Markup::new().text("a\0b").into_string(), "a\u{fffd}b");
+  }
+
+  #[test]
+  fn span() {
+    assert_eq!(
+      Markup::new()
+        .text("<i>")
+        .span(&[("color", r#"red" weight="bold"#), ("font", "<&>")])
+        .into_string(),
+      r#"<span color="red&quot; weight=&quot;bold" font="&lt;&amp;&gt;">&lt;i&gt;</span>"#
+    );
+  }
+
+  #[test]
+  fn push() {
+    let bold = Markup::new().text("<b>").span(&[("weight", "bold")]);
+    assert_eq!(
+      Markup::new().text("a&").push(bold).text("</span>").into_string(),
+      r#"a&amp;<span weight="bold">&lt;b&gt;</span>&lt;/span&gt;"#
+    );
+  }
+
+  // SSIDs are up to 32 arbitrary bytes, device names are whatever the device advertises.
+  #[test]
+  fn hostile_names() {
+    let ssid = r#"</span><span color="green">x"#;
+    assert_eq!(
+      crate::color(Markup::new().text(format!("▇ {ssid}")), crate::Color::Red).into_string(),
+      r#"<span color="red">▇ &lt;/span&gt;&lt;span color=&quot;green&quot;&gt;x</span>"#
+    );
+    assert_eq!(
+      crate::color(Markup::new().text("AT&T\0Free Wi-Fi"), crate::Color::Unspecified).into_string(),
+      "AT&amp;T\u{fffd}Free Wi-Fi"
+    );
+    let name = "Bob's <Headphones> & \u{1b}";
+    assert_eq!(
+      Markup::new().text(format!("█ {name}")).into_string(),
+      "█ Bob&apos;s &lt;Headphones&gt; &amp; &#x1b;"
+    );
+  }
+}
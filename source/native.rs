@@ -0,0 +1,135 @@
+// A fallback for when there's no Prometheus (and node_exporter) around: the same metrics are read
+// straight from procfs and sysfs and the last samples are kept in memory. Nothing survives a
+// restart (or a pause of the bar) so the sparklines start empty.
+
+use smol::stream::StreamExt as _;
+
+// The first sample of a rate needs two readings, it shouldn't take a whole step to show up.
+const WARMUP: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum Metric {
+  // The ratio of non-idle time, across all CPUs (like node_cpu_seconds_total{mode!="idle"}).
+  Cpu,
+  // In bytes per second.
+  Receive(String),
+  Transmit(String),
+  // The hottest thermal zone, in °C.
+  Temperature,
+}
+
+// Either a value as is or a pair of counters, the value being the ratio of their increases (e.g.:
+// bytes over seconds, busy time over total time).
+#[derive(Debug)]
+enum Reading {
+  Gauge(f64),
+  Counters(f64, f64),
+}
+
+// https://man.archlinux.org/man/proc_stat.5
+// cpu  user nice system idle iowait irq softirq steal guest guest_nice
+// Guest times are already accounted in user and nice.
+async fn cpu() -> std::io::Result<Reading> {
+  let stat = smol::fs::read_to_string("/proc/stat").await?;
+  let times = stat
+    .lines()
+    .find_map(|line| line.strip_prefix("cpu "))
+    .map(|times| {
+      times
+        .split_whitespace()
+        .take(8)
+        .map(str::parse::<f64>)
+        .collect::<Result<Vec<_>, _>>()
+    })
+    .ok_or_else(|| std::io::Error::other("no cpu line in /proc/stat"))?
+    .map_err(std::io::Error::other)?;
+  let total = times.iter().sum::<f64>();
+  let idle = times.get(3).copied().unwrap_or(0.);
+  Ok(Reading::Counters(total - idle, total))
+}
+
+// https://man.archlinux.org/man/proc_net.5
+// Inter-|   Receive                                                |  Transmit
+//  face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+//  wlan0: 1234567    1234    0    0    0     0          0         0   123456     123    0    0    0     0       0          0
+async fn network(device: &str, field: usize, start: std::time::Instant) -> std::io::Result<Reading> {
+  let dev = smol::fs::read_to_string("/proc/net/dev").await?;
+  let bytes = dev
+    .lines()
+    .filter_map(|line| line.split_once(':'))
+    .find(|(name, _)| name.trim() == device)
+    .and_then(|(_, counters)| counters.split_whitespace().nth(field))
+    .ok_or_else(|| std::io::Error::other(format!("no device {device:?} in /proc/net/dev")))?
+    .parse::<f64>()
+    .map_err(std::io::Error::other)?;
+  Ok(Reading::Counters(bytes, start.elapsed().as_secs_f64()))
+}
+
+// https://www.kernel.org/doc/Documentation/thermal/sysfs-api.txt
+// Temperatures are in millidegree Celsius.
+async fn temperature() -> std::io::Result<Reading> {
+  let mut zones = smol::fs::read_dir("/sys/class/thermal").await?;
+  let mut hottest: Option<f64> = None;
+  while let Some(zone) = zones.next().await {
+    let zone = zone?;
+    if !zone.file_name().to_string_lossy().starts_with("thermal_zone") {
+      continue;
+    }
+    // Some zones can't be read (e.g.: a sensor that's powered down).
+    match smol::fs::read_to_string(zone.path().join("temp")).await {
+      Ok(temperature) => match temperature.trim().parse::<f64>() {
+        Ok(temperature) => hottest = Some(hottest.unwrap_or(f64::MIN).max(temperature / 1000.)),
+        Err(error) => log::debug!("Invalid temperature in {:?}: {error:?}", zone.path()),
+      },
+      Err(error) => log::debug!("Failed to read {:?}: {error:?}", zone.path()),
+    }
+  }
+  hottest
+    .map(Reading::Gauge)
+    .ok_or_else(|| std::io::Error::other("no thermal zone in /sys/class/thermal"))
+}
+
+impl Metric {
+  async fn read(&self, start: std::time::Instant) -> std::io::Result<Reading> {
+    match self {
+      Self::Cpu => cpu().await,
+      Self::Receive(device) => network(device, 0, start).await,
+      Self::Transmit(device) => network(device, 8, start).await,
+      Self::Temperature => temperature().await,
+    }
+  }
+}
+
+// The last samples (at most points of them, the oldest first), one per prometheus::STEP so
+// sparklines look the same whatever the backend.
+pub fn samples(metric: Metric, points: usize) -> impl smol::stream::Stream<Item = std::io::Result<Vec<f64>>> {
+  async_stream::try_stream! {
+  let start = std::time::Instant::now();
+  let mut samples = std::collections::VecDeque::with_capacity(points);
+  let mut previous = metric.read(start).await?;
+  if let Reading::Gauge(value) = previous {
+    samples.push_back(value);
+    yield samples.iter().copied().collect();
+  }
+  let mut timer = smol::Timer::interval_at(start + WARMUP, crate::prometheus::STEP);
+  while timer.next().await.is_some() {
+    let current = metric.read(start).await?;
+    let value = match (&previous, &current) {
+      (_, Reading::Gauge(value)) => *value,
+      // Counters may be reset (e.g.: an interface that went down).
+      (Reading::Counters(value0, over0), Reading::Counters(value1, over1)) if over1 > over0 && value1 >= value0 => {
+        (value1 - value0) / (over1 - over0)
+      }
+      (Reading::Counters(..), Reading::Counters(..)) => 0.,
+      (Reading::Gauge(_), Reading::Counters(..)) => unreachable!(), // A metric is always read the same way.
+    };
+    log::trace!("Sample for {metric:?}: {value}");
+    previous = current;
+    if samples.len() == points {
+      samples.pop_front();
+    }
+    samples.push_back(value);
+    yield samples.iter().copied().collect();
+  }
+  }
+}
@@ -1,15 +1,17 @@
 mod query;
-use query::{instant, range};
-pub use query::{Error, MatrixResult, Value, VectorResult};
+pub use query::{Client, Error, MatrixResult, Metric, Value, VectorResult};
+use query::{Data, instant, range};
 
 pub const STEP: std::time::Duration = std::time::Duration::from_secs(60);
 
 pub async fn cpu(
+  client: &Client,
   executor: &smol::Executor<'static>,
   start: chrono::DateTime<chrono::offset::Local>,
   end: chrono::DateTime<chrono::offset::Local>,
 ) -> Result<Vec<MatrixResult>, Error> {
   range(
+    client,
     executor,
     r#"avg (sum (rate(node_cpu_seconds_total{mode!="idle"}[1m])) without (mode)) without (cpu)"#,
     start.timestamp(),
@@ -19,14 +21,58 @@ pub async fn cpu(
   .await
 }
 
+// For user-defined queries.
+pub async fn custom_range(
+  client: &Client,
+  executor: &smol::Executor<'static>,
+  query: &str,
+  start: chrono::DateTime<chrono::offset::Local>,
+  end: chrono::DateTime<chrono::offset::Local>,
+) -> Result<Vec<MatrixResult>, Error> {
+  range(client, executor, query, start.timestamp(), end.timestamp(), STEP.as_secs_f64()).await
+}
+
+// What a user-defined instant query is displayed as.
+pub enum Instant {
+  // A scalar (e.g.: from scalar(...) or a literal) is turned into a single series without any label.
+  Vector(Vec<VectorResult>),
+  // Displayed as is (e.g.: from a string literal).
+  String(String),
+}
+
+// For user-defined queries.
+pub async fn custom_instant(
+  client: &Client,
+  executor: &smol::Executor<'static>,
+  query: &str,
+  end: chrono::DateTime<chrono::offset::Local>,
+) -> Result<Instant, Error> {
+  match instant(client, executor, query, end.timestamp()).await? {
+    Data::Scalar(value) => Ok(Instant::Vector(vec![VectorResult {
+      metric: Metric::new(),
+      value,
+    }])),
+    Data::String(Value(_, string)) => Ok(Instant::String(string)),
+    data => data.vector().map(Instant::Vector),
+  }
+}
+
+// PromQL strings follow Go's escaping rules, only the backslash and the quote matter here.
+fn quote(value: &str) -> String {
+  format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 pub async fn download(
+  client: &Client,
   executor: &smol::Executor<'static>,
+  device: &str,
   start: chrono::DateTime<chrono::offset::Local>,
   end: chrono::DateTime<chrono::offset::Local>,
 ) -> Result<Vec<MatrixResult>, Error> {
   range(
+    client,
     executor,
-    r#"rate(node_network_receive_bytes_total{device="wlan0"}[1m])"#,
+    &format!("rate(node_network_receive_bytes_total{{device={}}}[1m])", quote(device)),
     start.timestamp(),
     end.timestamp(),
     STEP.as_secs_f64(),
@@ -35,11 +81,13 @@ pub async fn download(
 }
 
 pub async fn temperature(
+  client: &Client,
   executor: &smol::Executor<'static>,
   start: chrono::DateTime<chrono::offset::Local>,
   end: chrono::DateTime<chrono::offset::Local>,
 ) -> Result<Vec<MatrixResult>, Error> {
   range(
+    client,
     executor,
     r#"max (max_over_time(node_thermal_zone_temp[1m])) without (type, zone)"#,
     start.timestamp(),
@@ -50,13 +98,16 @@ pub async fn temperature(
 }
 
 pub async fn upload(
+  client: &Client,
   executor: &smol::Executor<'static>,
+  device: &str,
   start: chrono::DateTime<chrono::offset::Local>,
   end: chrono::DateTime<chrono::offset::Local>,
 ) -> Result<Vec<MatrixResult>, Error> {
   range(
+    client,
     executor,
-    r#"rate(node_network_transmit_bytes_total{device="wlan0"}[1m])"#,
+    &format!("rate(node_network_transmit_bytes_total{{device={}}}[1m])", quote(device)),
     start.timestamp(),
     end.timestamp(),
     STEP.as_secs_f64(),
@@ -64,9 +115,14 @@ pub async fn upload(
   .await
 }
 
-pub async fn wifi(executor: &smol::Executor<'static>, end: chrono::DateTime<chrono::offset::Local>) -> Result<Vec<VectorResult>, Error> {
+pub async fn wifi(
+  client: &Client,
+  executor: &smol::Executor<'static>,
+  end: chrono::DateTime<chrono::offset::Local>,
+) -> Result<Vec<VectorResult>, Error> {
   // iwd exposes a dbus interface but not the signal strength.
   instant(
+    client,
     executor,
     r#"
       # {bssid="00:11:22:33:44:55", device="wlan0", instance="localhost:9100", job="node_exporter", ssid="SSID"} 0
@@ -78,5 +134,6 @@ pub async fn wifi(executor: &smol::Executor<'static>, end: chrono::DateTime<chro
     "#,
     end.timestamp(),
   )
-  .await
+  .await?
+  .vector()
 }
@@ -2,8 +2,10 @@ use smol::stream::StreamExt as _;
 
 pub type Metric = std::collections::HashMap<String, String>;
 
+// The timestamp (possibly fractional) and the sample, as a string since it may not be a number.
 #[derive(Debug, serde::Deserialize)]
-pub struct Value(pub i64, pub String);
+#[allow(dead_code)] // The timestamp is only deserialized, the points are evenly spaced.
+pub struct Value(pub f64, pub String);
 
 // { "status": "success",
 //   "data": { "resultType": "matrix",
@@ -36,80 +38,286 @@ pub struct VectorResult {
   pub value: Value,
 }
 
+// { "status": "success",
+//   "data": { "resultType": "scalar",
+//             "result": [ 1720256880.334, "1" ] }
+// }
+// Strings are the same, except for the resultType.
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "resultType", content = "result")]
-enum Data {
+pub enum Data {
   Matrix(Vec<MatrixResult>),
   Vector(Vec<VectorResult>),
+  Scalar(Value),
+  String(Value),
 }
 
-#[derive(Debug, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-enum Status {
-  Success,
+impl Data {
+  fn result_type(&self) -> &'static str {
+    match self {
+      Self::Matrix(_) => "matrix",
+      Self::Vector(_) => "vector",
+      Self::Scalar(_) => "scalar",
+      Self::String(_) => "string",
+    }
+  }
+
+  pub fn matrix(self) -> Result<Vec<MatrixResult>, Error> {
+    match self {
+      Self::Matrix(results) => Ok(results),
+      data => Err(Error::ResultType {
+        expected: "matrix",
+        actual: data.result_type(),
+      }),
+    }
+  }
+
+  pub fn vector(self) -> Result<Vec<VectorResult>, Error> {
+    match self {
+      Self::Vector(results) => Ok(results),
+      data => Err(Error::ResultType {
+        expected: "vector",
+        actual: data.result_type(),
+      }),
+    }
+  }
 }
 
+// https://prometheus.io/docs/prometheus/latest/querying/api/#format-overview
+// { "status": "error",
+//   "errorType": "bad_data",
+//   "error": "invalid parameter \"query\": 1:1: parse error: unexpected end of input",
+//   "warnings": [ ... ] // Optional, also for successes.
+// }
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Response {
-  #[allow(dead_code)] // For deserialization only.
-  status: Status,
-  data: Data,
+#[serde(tag = "status")]
+enum Response {
+  #[serde(rename_all = "camelCase")]
+  Success {
+    data: Data,
+    #[serde(default)]
+    warnings: Vec<String>,
+  },
+  #[serde(rename_all = "camelCase")]
+  Error {
+    error_type: String,
+    error: String,
+    #[serde(default)]
+    warnings: Vec<String>,
+  },
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
   #[error("HTTP error")]
   HTTP(#[from] http::Error),
+  #[error("HTTP status {0}")]
+  HTTPStatus(http::StatusCode),
   #[error("Hyper error")]
   Hyper(#[from] hyper::Error),
+  #[error("Invalid DNS name")]
+  InvalidDNSName(#[from] futures_rustls::pki_types::InvalidDnsNameError),
+  #[error("Invalid header")]
+  InvalidHeader(#[from] http::header::InvalidHeaderValue),
+  #[error("Invalid URI")]
+  InvalidURI(#[from] http::uri::InvalidUri),
   #[error("IO error")]
   IO(#[from] std::io::Error),
   #[error("JSON error")]
   JSON(#[from] serde_json::Error),
+  #[error("Prometheus error ({kind}): {message}")]
+  Prometheus {
+    kind: String,
+    message: String,
+    warnings: Vec<String>,
+  },
+  #[error("Unexpected result type (expected {expected}, got {actual})")]
+  ResultType { expected: &'static str, actual: &'static str },
+  #[error("Timeout")]
+  Timeout,
+  #[error("TLS error")]
+  TLS(#[from] futures_rustls::rustls::Error),
 }
 
-async fn common(
-  executor: &smol::Executor<'static>,
-  query: &str,
-  uri: http::uri::Builder,
-  body: &mut form_urlencoded::Serializer<'_, String>,
-) -> std::result::Result<Response, Error> {
-  let uri = uri.scheme("http").authority("localhost:9090").build()?;
-  let request = http::Request::builder()
-    .uri(&uri)
-    .header(
-      http::header::HOST,
-      uri
-        .authority()
-        .unwrap() // Unwrap: set above.
-        .as_str(),
-    )
-    .header(http::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-    .method(http::method::Method::POST)
-    .body(body.append_pair("query", query).finish())?;
+trait Connection: smol::io::AsyncRead + smol::io::AsyncWrite + Send + Unpin {}
+impl<T: smol::io::AsyncRead + smol::io::AsyncWrite + Send + Unpin> Connection for T {}
+
+type Sender = hyper::client::conn::http1::SendRequest<String>;
+
+// Idle keep-alive connections beyond this are closed (there's usually one per Prometheus block,
+// waiting for its next query).
+const IDLE_CONNECTIONS: usize = 8;
+
+// Where and how to reach the Prometheus HTTP API (which is also implemented by VictoriaMetrics,
+// Thanos, ...), possibly behind a reverse proxy.
+pub struct Client {
+  // The base URI, its path is used as a prefix.
+  uri: http::Uri,
+  authorization: Option<http::HeaderValue>,
+  timeout: std::time::Duration,
+  tls: Option<futures_rustls::TlsConnector>,
+  // HTTP/1.1 connections kept alive between queries, to avoid a TCP (and TLS) handshake each time.
+  // Concurrent queries each take their own.
+  idle: std::cell::RefCell<Vec<Sender>>,
+}
+
+impl Client {
+  pub fn new(config: &crate::config::Prometheus) -> Result<Self, Error> {
+    let uri: http::Uri = config.url.parse()?;
+    let authorization = match &config.authorization {
+      None => None,
+      Some(crate::config::Authorization::Basic { username, password }) => Some(format!(
+        "Basic {}",
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, format!("{username}:{}", password.0))
+      )),
+      Some(crate::config::Authorization::Bearer { token }) => Some(format!("Bearer {}", token.0)),
+    }
+    .map(|authorization| {
+      http::HeaderValue::try_from(authorization).map(|mut authorization| {
+        authorization.set_sensitive(true);
+        authorization
+      })
+    })
+    .transpose()?;
+    let tls = match uri.scheme() {
+      Some(scheme) if *scheme == http::uri::Scheme::HTTPS => {
+        let certificates = rustls_native_certs::load_native_certs();
+        for error in certificates.errors {
+          log::warn!("Failed to load a native certificate: {error:?}");
+        }
+        let mut roots = futures_rustls::rustls::RootCertStore::empty();
+        let (added, ignored) = roots.add_parsable_certificates(certificates.certs);
+        log::debug!("Loaded {added} native certificates ({ignored} ignored)");
+        let mut tls = futures_rustls::rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+          futures_rustls::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Some(futures_rustls::TlsConnector::from(std::sync::Arc::new(tls)))
+      }
+      _ => None,
+    };
+    Ok(Self {
+      uri,
+      authorization,
+      // Unwrap: validated with the configuration.
+      timeout: std::time::Duration::try_from_secs_f64(config.timeout).unwrap(),
+      tls,
+      idle: std::cell::RefCell::new(Vec::new()),
+    })
+  }
+
+  // The idle connections may not survive a suspend (the server or something in between may have
+  // dropped them meanwhile), reusing one would hang until the timeout.
+  pub fn disconnect(&self) {
+    self.idle.borrow_mut().clear();
+  }
+
+  fn checkout(&self) -> Option<Sender> {
+    let mut idle = self.idle.borrow_mut();
+    while let Some(sender) = idle.pop() {
+      if !sender.is_closed() {
+        return Some(sender);
+      }
+    }
+    None
+  }
+
+  fn checkin(&self, sender: Sender) {
+    let mut idle = self.idle.borrow_mut();
+    if !sender.is_closed() && idle.len() < IDLE_CONNECTIONS {
+      idle.push(sender);
+    }
+  }
+}
 
+async fn connect(client: &Client, executor: &smol::Executor<'static>) -> std::result::Result<Sender, Error> {
+  let authority = client.uri.authority().unwrap(); // Unwrap: validated with the configuration.
   // https://github.com/smol-rs/smol/blob/master/examples/hyper-client.rs
-  assert_eq!(request.uri().scheme(), Some(&http::uri::Scheme::HTTP));
-  let host = request.uri().host().unwrap(); // Unwrap: set above.
+  // IPv6 addresses are bracketed in URIs, but not in socket addresses nor server names.
+  let host = authority.host().trim_start_matches('[').trim_end_matches(']');
   let stream = {
-    let port = request.uri().port_u16().unwrap_or(80);
+    let port = authority.port_u16().unwrap_or(match client.tls {
+      Some(_) => 443,
+      None => 80,
+    });
     smol::net::TcpStream::connect((host, port)).await?
   };
-  let (mut sender, connection) = hyper::client::conn::http1::handshake(smol_hyper::rt::FuturesIo::new(stream)).await?;
+  let stream: Box<dyn Connection> = match &client.tls {
+    Some(tls) => Box::new(
+      tls
+        .connect(futures_rustls::pki_types::ServerName::try_from(host.to_string())?, stream)
+        .await?,
+    ),
+    None => Box::new(stream),
+  };
+  let (sender, connection) = hyper::client::conn::http1::handshake(smol_hyper::rt::FuturesIo::new(stream)).await?;
   executor
     .spawn(async move {
       // From my understanding, this is weird but okay: internally, hyper uses a channel to
       // communicate between the sender and the connection, so if the connection gets closed
-      // the channel should too, which would bubble up to the sender.
+      // the channel should too, which would bubble up to the sender. Once the sender is dropped
+      // (e.g.: too many idle connections or a timeout), the connection ends.
       if let Err(e) = connection.await {
         log::warn!("Connection failed: {:?}", e);
       }
     })
     .detach();
-  let response = sender.send_request(request).await?;
+  Ok(sender)
+}
+
+async fn send(
+  client: &Client,
+  executor: &smol::Executor<'static>,
+  request: impl Fn() -> std::result::Result<http::Request<String>, Error>,
+) -> std::result::Result<(Sender, http::Response<hyper::body::Incoming>), Error> {
+  if let Some(mut sender) = client.checkout() {
+    // The server may have closed an idle connection at any time (e.g.: its keep-alive timeout has
+    // expired). Queries are read-only so they can safely be retried on a new connection.
+    match async {
+      sender.ready().await?;
+      sender.send_request(request()?).await.map_err(Error::from)
+    }
+    .await
+    {
+      Ok(response) => return Ok((sender, response)),
+      Err(error) => log::debug!("Failed to reuse a connection: {error:?}"),
+    }
+  }
+  let mut sender = connect(client, executor).await?;
+  let response = sender.send_request(request()?).await?;
+  Ok((sender, response))
+}
+
+async fn request(
+  client: &Client,
+  executor: &smol::Executor<'static>,
+  query: &str,
+  path: &str,
+  body: &mut form_urlencoded::Serializer<'_, String>,
+) -> std::result::Result<Data, Error> {
+  let authority = client.uri.authority().unwrap(); // Unwrap: validated with the configuration.
+  let body = body.append_pair("query", query).finish();
+  let request = || {
+    // The origin form is used as some reverse proxies don't like absolute URIs.
+    let mut request = http::Request::builder()
+      .uri(format!("{}{path}", client.uri.path().trim_end_matches('/')))
+      .header(http::header::HOST, authority.as_str())
+      .header(http::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+      .method(http::method::Method::POST);
+    if let Some(authorization) = &client.authorization {
+      request = request.header(http::header::AUTHORIZATION, authorization);
+    }
+    Ok(request.body(body.clone())?)
+  };
+
+  let (sender, response) = send(client, executor, request).await?;
   log::trace!("HTTP response: {response:?}");
+  let status = response.status();
   let body: std::result::Result<Vec<u8>, _> = http_body_util::BodyStream::new(response.into_body())
     .try_fold(Vec::new(), |mut body, chunk| {
       if let Some(chunk) = chunk.data_ref() {
@@ -118,48 +326,86 @@ async fn common(
       Ok(body)
     })
     .await;
-  Ok(serde_json::from_slice(&body?)?)
+  let body = body?;
+  // The whole response was read, the connection can serve another query.
+  client.checkin(sender);
+  // Prometheus answers errors with a JSON body (and a 4xx or 5xx status) but something in between
+  // (e.g.: a reverse proxy) may not.
+  let response = match serde_json::from_slice(&body) {
+    Ok(response) => response,
+    Err(_) if !status.is_success() => return Err(Error::HTTPStatus(status)),
+    Err(error) => return Err(error.into()),
+  };
+  match response {
+    Response::Success { data, warnings } => {
+      for warning in warnings {
+        log::warn!("Prometheus warning for {query:?}: {warning}");
+      }
+      Ok(data)
+    }
+    Response::Error {
+      error_type,
+      error,
+      warnings,
+    } => {
+      // Often the reason of the error (e.g.: a partial response from a remote storage).
+      for warning in &warnings {
+        log::warn!("Prometheus warning for {query:?}: {warning}");
+      }
+      Err(Error::Prometheus {
+        kind: error_type,
+        message: error,
+        warnings,
+      })
+    }
+  }
 }
 
-pub async fn instant(executor: &smol::Executor<'static>, query: &str, time: i64) -> std::result::Result<Vec<VectorResult>, Error> {
-  Ok(
-    match common(
-      executor,
-      query,
-      http::uri::Builder::new().path_and_query("/api/v1/query"),
-      form_urlencoded::Serializer::new(String::new()).append_pair("time", &time.to_string()),
-    )
-    .await?
-    .data
-    {
-      Data::Vector(results) => results,
-      _ => unreachable!(),
-    },
+// Without a timeout, a hung server would stall the stream forever.
+async fn common(
+  client: &Client,
+  executor: &smol::Executor<'static>,
+  query: &str,
+  path: &str,
+  body: &mut form_urlencoded::Serializer<'_, String>,
+) -> std::result::Result<Data, Error> {
+  smol::future::or(request(client, executor, query, path, body), async {
+    smol::Timer::after(client.timeout).await;
+    Err(Error::Timeout)
+  })
+  .await
+}
+
+// Instant queries may return any result type (e.g.: scalar(...), a range vector selector, ...).
+pub async fn instant(client: &Client, executor: &smol::Executor<'static>, query: &str, time: i64) -> std::result::Result<Data, Error> {
+  common(
+    client,
+    executor,
+    query,
+    "/api/v1/query",
+    form_urlencoded::Serializer::new(String::new()).append_pair("time", &time.to_string()),
   )
+  .await
 }
 
 pub async fn range(
+  client: &Client,
   executor: &smol::Executor<'static>,
   query: &str,
   start: i64,
   end: i64,
   step: f64,
 ) -> std::result::Result<Vec<MatrixResult>, Error> {
-  Ok(
-    match common(
-      executor,
-      query,
-      http::uri::Builder::new().path_and_query("/api/v1/query_range"),
-      form_urlencoded::Serializer::new(String::new())
-        .append_pair("start", &start.to_string())
-        .append_pair("end", &end.to_string())
-        .append_pair("step", &step.to_string()),
-    )
-    .await?
-    .data
-    {
-      Data::Matrix(results) => results,
-      _ => unreachable!(),
-    },
+  common(
+    client,
+    executor,
+    query,
+    "/api/v1/query_range",
+    form_urlencoded::Serializer::new(String::new())
+      .append_pair("start", &start.to_string())
+      .append_pair("end", &end.to_string())
+      .append_pair("step", &step.to_string()),
   )
+  .await?
+  .matrix()
 }
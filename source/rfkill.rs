@@ -0,0 +1,60 @@
+// Radios can be blocked regardless of their driver: in software (e.g.: airplane mode, or the state
+// restored by systemd-rfkill on boot) or in hardware (a physical switch). A blocked Bluetooth
+// adapter can't be powered on, BlueZ only answers org.bluez.Error.Blocked.
+// https://www.kernel.org/doc/html/latest/driver-api/rfkill.html
+// https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/rfkill.h
+
+use smol::{io::AsyncWriteExt as _, stream::StreamExt as _};
+
+// enum rfkill_operation
+const OP_CHANGE: u8 = 2;
+
+#[derive(Debug)]
+pub struct Switch {
+  // As in /sys/class/rfkill/rfkill<index>.
+  pub index: u32,
+  pub soft: bool,
+  pub hard: bool,
+}
+
+async fn blocked(path: &std::path::Path) -> std::io::Result<bool> {
+  Ok(smol::fs::read_to_string(path).await?.trim() != "0")
+}
+
+// The switch of a device is a child of it in sysfs (e.g.: /sys/class/bluetooth/hci0/rfkill1).
+// None when the device has no switch (or isn't there).
+pub async fn switch(device: &std::path::Path) -> std::io::Result<Option<Switch>> {
+  let mut entries = match smol::fs::read_dir(device).await {
+    Ok(entries) => entries,
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(error) => return Err(error),
+  };
+  while let Some(entry) = entries.next().await {
+    let entry = entry?;
+    let Some(index) = entry
+      .file_name()
+      .to_str()
+      .and_then(|name| name.strip_prefix("rfkill"))
+      .and_then(|index| index.parse().ok())
+    else {
+      continue;
+    };
+    return Ok(Some(Switch {
+      index,
+      soft: blocked(&entry.path().join("soft")).await?,
+      hard: blocked(&entry.path().join("hard")).await?,
+    }));
+  }
+  Ok(None)
+}
+
+// Lifts the soft block. /dev/rfkill is usually writable by the seat's user (through uaccess).
+pub async fn unblock(index: u32) -> std::io::Result<()> {
+  // struct rfkill_event (packed): idx, type (ignored for a single device), op, soft, hard.
+  let mut event = [0; 8];
+  event[..4].copy_from_slice(&index.to_ne_bytes());
+  event[5] = OP_CHANGE;
+  let mut rfkill = smol::fs::OpenOptions::new().write(true).open("/dev/rfkill").await?;
+  rfkill.write_all(&event).await?;
+  rfkill.flush().await
+}
@@ -1,163 +1,142 @@
+// The default sink (and source) is followed over PulseAudio's native protocol (see pulse.rs), which
+// pipewire-pulse implements too.
 // https://gitlab.freedesktop.org/pipewire/wireplumber/-/issues/651
 // No D-Bus interface exists to listen for changes in volume.
-// Sometimes, PipeWire is acting up so I rely on PulseAudio tools for the times I need to fallback.
 
-use smol::{io::AsyncBufReadExt as _, stream::StreamExt as _};
+use crate::pulse;
 
-#[derive(Debug)]
-pub enum Status {
-  Mute,
-  Volume(u8),
-}
+pub use pulse::Kind;
 
-// https://github.com/pulseaudio/pulseaudio/blob/c1990dd02647405b0c13aab59f75d05cbb202336/src/utils/pactl.c#L2205
-#[derive(Debug, serde::Deserialize)]
-#[serde(rename_all = "kebab-case")]
-enum Type {
-  Change,
-  #[serde(other)]
-  Unknown,
+fn default_name(kind: Kind) -> &'static str {
+  match kind {
+    Kind::Sink => "@DEFAULT_SINK@",
+    Kind::Source => "@DEFAULT_SOURCE@",
+  }
 }
 
-#[derive(Debug, serde::Deserialize)]
-#[serde(rename_all = "kebab-case")]
-enum Facility {
-  Sink,
-  #[serde(other)]
-  Unknown,
+#[derive(Debug)]
+pub struct Status {
+  // The description of the default device (or its name, without one).
+  pub device: String,
+  pub mute: bool,
+  // In percent, it may go above 100.
+  pub volume: u16,
+  // Whether an application is recording from any source (always false for sinks).
+  pub recording: bool,
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct Event {
-  index: u32,
-  event: Type,
-  on: Facility,
+impl Status {
+  fn new(device: &pulse::Device, recording: bool) -> Self {
+    Self {
+      device: device.description.clone().unwrap_or_else(|| device.name.clone()),
+      mute: device.mute,
+      volume: device.percent().round().min(u16::MAX.into()) as u16,
+      recording,
+    }
+  }
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct Volume {
-  value_percent: String,
+// None when there's no device at all.
+async fn default(connection: &mut pulse::Connection, kind: Kind) -> std::io::Result<Option<pulse::Device>> {
+  match connection.server().await?.default(kind) {
+    Some(name) => Ok(Some(connection.device(kind, name).await?)),
+    None => Ok(None),
+  }
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct Sink {
-  index: u32,
-  mute: bool,
-  volume: std::collections::HashMap<String, Volume>,
+// Any source counts, not only the default one: an application may record from another microphone
+// (or a monitor) and the indicator is there to show that something listens.
+async fn recording(connection: &mut pulse::Connection, kind: Kind) -> std::io::Result<bool> {
+  match kind {
+    Kind::Source => Ok(!connection.source_outputs().await?.is_empty()),
+    Kind::Sink => Ok(false),
+  }
 }
 
-pub async fn statuses() -> impl smol::stream::Stream<Item = std::io::Result<Status>> {
-  let stream = async_stream::try_stream! {
-  let mut pactl = smol::process::Command::new("pactl")
-    .args(["--format", "json", "subscribe"])
-    .stdout(smol::process::Stdio::piped())
-    .spawn()?;
-  let mut lines = smol::io::BufReader::new(
-    pactl.stdout.take().unwrap(), // Unwrap: stdout is piped?
-  )
-  .split(b'\n');
-  while let Some(line) = lines.next().await {
-    let line = line?;
-    log::trace!("pactl subscribe:\n{:?}", String::from_utf8_lossy(&line));
-    if let Event {
-      index,
-      // It's important to aggressively filter upfront because running pactl will trigger another
-      // new/remove client event, which will be fed back...
-      event: Type::Change,
-      on: Facility::Sink,
-    } = serde_json::from_slice(&line)?
-    {
-      let sinks = smol::process::Command::new("pactl")
-        .args(["--format", "json", "list", "sinks"])
-        .output()
-        .await?;
-      log::trace!("pactl list sinks:\n{sinks:?}");
-      for sink in serde_json::from_slice::<Vec<Sink>>(&sinks.stdout)?
-        .iter()
-        .filter(|sink| sink.index == index)
-      {
-        match sink.mute {
-          true => {
-            yield Status::Mute;
-            break;
-          }
-          false => {
-            if let Some(volume) = sink.volume.iter().next() {
-              if let Some(volume) = volume.1.value_percent.strip_suffix('%') {
-                if let Ok(volume) = volume.parse() {
-                  yield Status::Volume(volume);
-                  break;
-                }
-              }
-            }
-            log::debug!("pactl list sinks: no match for {index}");
-          }
-        }
+// The default device is followed: the server notifies when it changes (e.g.: a USB DAC was plugged
+// in) and changes to other devices are ignored. For sources, applications starting or stopping to
+// record are followed too.
+pub async fn statuses(kind: Kind) -> impl smol::stream::Stream<Item = std::io::Result<Option<Status>>> {
+  async_stream::try_stream! {
+  let mut connection = pulse::Connection::connect().await?;
+  let mask = match kind {
+    Kind::Sink => kind.mask(),
+    Kind::Source => kind.mask() | pulse::SUBSCRIPTION_MASK_SOURCE_OUTPUT,
+  };
+  connection.subscribe(mask | pulse::SUBSCRIPTION_MASK_SERVER).await?;
+  let mut device = default(&mut connection, kind).await?;
+  let mut recording = self::recording(&mut connection, kind).await?;
+  yield device.as_ref().map(|device| Status::new(device, recording)); // Push the starting value.
+  loop {
+    let event = connection.next_event().await?;
+    log::trace!("pulse event: {event:?}");
+    match event {
+      pulse::Event {
+        facility: pulse::FACILITY_SERVER,
+        ..
+      } => device = default(&mut connection, kind).await?,
+      pulse::Event {
+        facility,
+        kind: pulse::TYPE_CHANGE,
+        index,
+      } if facility == kind.facility() && device.as_ref().is_some_and(|device| device.index == index) => {
+        device = default(&mut connection, kind).await?;
       }
+      // Applications starting or stopping to record.
+      pulse::Event {
+        facility: pulse::FACILITY_SOURCE_OUTPUT,
+        ..
+      } => recording = self::recording(&mut connection, kind).await?,
+      _ => continue,
     }
+    yield device.as_ref().map(|device| Status::new(device, recording));
+  }
   }
-  // Push an error in case the stream unexpectedly ends (e.g.: the command exits because the
-  // connection to the server is broken).
-  let status = pactl.status().await?;
-  Err(std::io::Error::new(
-    std::io::ErrorKind::Other,
-    format!("pactl subscribe ended: {status:?}"),
-  ))?; // try_stream! doesn't allow to yield or return an error directly.
-  };
-  smol::stream::once(status().await) // Push the starting value.
-    .chain(stream)
 }
 
-async fn status() -> std::io::Result<Status> {
-  let mute = smol::process::Command::new("bash").args(["-c", "volume_mute_get"]).output().await?;
-  match String::from_utf8_lossy(&mute.stdout).trim() {
-    "no" => {
-      let volume = smol::process::Command::new("bash").args(["-c", "volume_get"]).output().await?;
-      match String::from_utf8_lossy(&volume.stdout).trim().parse() {
-        Ok(volume) => Ok(Status::Volume(volume)),
-        Err(error) => Err(std::io::Error::new(
-          std::io::ErrorKind::Other,
-          format!("couldn't get volume ({volume:?}, {error:?})"),
-        )),
-      }
-    }
-    "yes" => Ok(Status::Mute),
-    _ => Err(std::io::Error::new(
-      std::io::ErrorKind::Other,
-      format!("couldn't get mute ({mute:?})"),
-    )),
+// Makes the next device (in the server's order) the default one.
+pub async fn next(kind: Kind) -> std::io::Result<()> {
+  let mut connection = pulse::Connection::connect().await?;
+  let server = connection.server().await?;
+  let devices = connection.devices(kind).await?;
+  let next = devices
+    .iter()
+    .position(|device| Some(device.name.as_str()) == server.default(kind))
+    .map_or(0, |position| (position + 1) % devices.len());
+  match devices.get(next) {
+    Some(device) => connection.set_default(kind, &device.name).await,
+    None => Ok(()),
   }
 }
 
-pub async fn mute() -> std::io::Result<()> {
-  let status = smol::process::Command::new("bash").args(["-c", "volume_mute"]).status().await?;
-  match status.success() {
-    true => Ok(()),
-    false => Err(std::io::Error::new(
-      std::io::ErrorKind::Other,
-      format!("couldn't mute volume ({status:?})"),
-    )),
-  }
+pub async fn mute(kind: Kind) -> std::io::Result<()> {
+  let mut connection = pulse::Connection::connect().await?;
+  let device = connection.device(kind, default_name(kind)).await?;
+  connection.set_mute(kind, device.index, !device.mute).await
 }
 
-pub async fn up() -> std::io::Result<()> {
-  let status = smol::process::Command::new("bash").args(["-c", "volume_up"]).status().await?;
-  match status.success() {
-    true => Ok(()),
-    false => Err(std::io::Error::new(
-      std::io::ErrorKind::Other,
-      format!("couldn't up volume ({status:?})"),
-    )),
-  }
+// Each channel is changed by the step (in percent), balance is kept as much as possible. Going
+// up stops at the maximum (in percent) but a volume that's already above isn't lowered.
+async fn change(kind: Kind, step: i32, maximum: u16) -> std::io::Result<()> {
+  let mut connection = pulse::Connection::connect().await?;
+  let device = connection.device(kind, default_name(kind)).await?;
+  let step = i64::from(step) * i64::from(pulse::VOLUME_NORM) / 100;
+  let maximum = i64::from(maximum) * i64::from(pulse::VOLUME_NORM) / 100;
+  let volume = device
+    .volume
+    .iter()
+    .map(|channel| {
+      let channel = i64::from(*channel);
+      (channel + step).clamp(0, maximum.max(channel)) as u32
+    })
+    .collect::<Vec<_>>();
+  connection.set_volume(kind, device.index, &volume).await
 }
 
-pub async fn down() -> std::io::Result<()> {
-  let status = smol::process::Command::new("bash").args(["-c", "volume_down"]).status().await?;
-  match status.success() {
-    true => Ok(()),
-    false => Err(std::io::Error::new(
-      std::io::ErrorKind::Other,
-      format!("couldn't down volume ({status:?})"),
-    )),
-  }
+pub async fn up(kind: Kind, step: u8, maximum: u16) -> std::io::Result<()> {
+  change(kind, step.into(), maximum).await
+}
+
+pub async fn down(kind: Kind, step: u8, maximum: u16) -> std::io::Result<()> {
+  change(kind, -i32::from(step), maximum).await
 }